@@ -1,48 +1,217 @@
 use crate::class::LoxInstance;
+use crate::convert::{FromLox, FromLoxArgs, IntoLox};
 use crate::environment::Environment;
 use crate::expressions::Var;
 use crate::interpreter::Interpreter;
-use crate::lox_error::LoxError;
+use crate::lox_error::{LoxError, SourceFile};
+use crate::module::LoxModule;
 use crate::object::Object;
 use crate::statements::Statement;
-use crate::tokens::{Token, TokenType};
+use crate::tokens::Token;
 use std::fmt;
+use std::ops::Deref;
+use std::rc::Rc;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 pub trait Callable: fmt::Debug {
     fn call(&self, interpreter: &mut Interpreter, args: &[Object]) -> Result<Object, LoxError>;
     fn arity(&self) -> usize;
     fn name(&self) -> String;
+
+    /// How the callable prints in Lox.
+    fn display(&self) -> String {
+        format!("<fn {}>", self.name())
+    }
+
+    /// A variadic callable accepts `arity()` or more arguments.
+    fn is_variadic(&self) -> bool {
+        false
+    }
+
+    /// The class a method was declared in, shown in backtraces.
+    fn class_name(&self) -> Option<String> {
+        None
+    }
 }
 
 impl fmt::Display for dyn Callable {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.name())
+        write!(f, "{}", self.display())
     }
 }
 
-impl PartialEq for dyn Callable {
-    fn eq(&self, other: &Self) -> bool {
-        self.name() == other.name() && self.arity() == other.arity()
+type NativeFn = dyn Fn(&Args) -> Result<Object, LoxError>;
+
+/// A function implemented in Rust, registered with
+/// `Interpreter::define_native`.
+pub struct NativeFunction {
+    name: String,
+    arity: usize,
+    variadic: bool,
+    function: Box<NativeFn>,
+}
+
+impl NativeFunction {
+    pub fn new<F, R>(name: impl Into<String>, arity: usize, function: F) -> Self
+    where
+        F: Fn(&Args) -> Result<R, LoxError> + 'static,
+        R: IntoLox,
+    {
+        Self {
+            name: name.into(),
+            arity,
+            variadic: false,
+            function: Box::new(move |args| function(args).map(IntoLox::into_lox)),
+        }
+    }
+
+    /// Like `new`, but accepts `min_arity` or more arguments.
+    pub fn variadic<F, R>(name: impl Into<String>, min_arity: usize, function: F) -> Self
+    where
+        F: Fn(&Args) -> Result<R, LoxError> + 'static,
+        R: IntoLox,
+    {
+        Self {
+            variadic: true,
+            ..Self::new(name, min_arity, function)
+        }
     }
 }
 
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<native fn {}>", self.name)
+    }
+}
+
+impl Callable for NativeFunction {
+    fn call(&self, _: &mut Interpreter, args: &[Object]) -> Result<Object, LoxError> {
+        (self.function)(&Args {
+            function: &self.name,
+            values: args,
+        })
+    }
+    fn arity(&self) -> usize {
+        self.arity
+    }
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+    fn is_variadic(&self) -> bool {
+        self.variadic
+    }
+    fn display(&self) -> String {
+        "<native fn>".to_string()
+    }
+}
+
+/// The arguments of a native call. Derefs to the argument slice and adds
+/// typed accessors that report which argument had the wrong type.
 #[derive(Debug)]
-pub struct Clock;
+pub struct Args<'a> {
+    function: &'a str,
+    values: &'a [Object],
+}
+
+impl<'a> Args<'a> {
+    pub(crate) fn new(function: &'a str, values: &'a [Object]) -> Self {
+        Self { function, values }
+    }
 
-impl Callable for Clock {
-    fn call(&self, _: &mut Interpreter, _: &[Object]) -> Result<Object, LoxError> {
-        let start = SystemTime::now();
+    pub fn num(&self, index: usize) -> Result<f64, LoxError> {
+        self.value(index)
+            .get_v_num()
+            .map_err(|e| self.in_arg(e, index))
+    }
+
+    pub fn string(&self, index: usize) -> Result<String, LoxError> {
+        self.value(index)
+            .get_v_string()
+            .map_err(|e| self.in_arg(e, index))
+    }
+
+    /// Converts the argument at `index` to any `FromLox` type.
+    pub fn arg<T: FromLox>(&self, index: usize) -> Result<T, LoxError> {
+        T::from_lox(self.value(index)).map_err(|e| self.in_arg(e, index))
+    }
+
+    /// Converts all arguments at once, e.g. into `(String, usize)`.
+    pub fn extract<T: FromLoxArgs>(&self) -> Result<T, LoxError> {
+        T::from_lox_args(self.values)
+    }
+
+    // Missing trailing arguments of a variadic call read as nil.
+    fn value(&self, index: usize) -> &Object {
+        self.values.get(index).unwrap_or(&Object::Nil)
+    }
+
+    fn in_arg(&self, err: LoxError, index: usize) -> LoxError {
+        match err {
+            LoxError::Diagnostic(d) => d
+                .with_note(format!("in argument {} of '{}'", index + 1, self.function))
+                .into(),
+            e => e,
+        }
+    }
+}
+
+impl Deref for Args<'_> {
+    type Target = [Object];
+
+    fn deref(&self) -> &[Object] {
+        self.values
+    }
+}
+
+pub(crate) fn clock() -> NativeFunction {
+    NativeFunction::new("clock", 0, |_| {
+        let now = SystemTime::now();
         Ok(Object::Number(
-            start.duration_since(UNIX_EPOCH).unwrap().as_secs_f64(),
+            now.duration_since(UNIX_EPOCH).unwrap().as_secs_f64(),
         ))
+    })
+}
+
+/// The `Math` namespace: functions of numbers and a few constants.
+pub(crate) fn math() -> LoxModule {
+    fn unary(name: &str, f: fn(f64) -> f64) -> NativeFunction {
+        NativeFunction::new(name, 1, move |args| Ok(f(args.num(0)?)))
     }
-    fn arity(&self) -> usize {
-        0
+    fn fold(name: &str, f: fn(f64, f64) -> f64) -> NativeFunction {
+        NativeFunction::variadic(name, 1, move |args| {
+            let first = args.num(0)?;
+            (1..args.len()).try_fold(first, |acc, i| Ok(f(acc, args.num(i)?)))
+        })
     }
-    fn name(&self) -> String {
-        "clock".to_string()
+
+    let functions = [
+        unary("sqrt", f64::sqrt),
+        unary("abs", f64::abs),
+        unary("floor", f64::floor),
+        unary("ceil", f64::ceil),
+        unary("round", f64::round),
+        unary("sin", f64::sin),
+        unary("cos", f64::cos),
+        unary("tan", f64::tan),
+        unary("log", f64::ln),
+        unary("exp", f64::exp),
+        fold("min", f64::min),
+        fold("max", f64::max),
+        NativeFunction::new("pow", 2, |args| Ok(args.num(0)?.powf(args.num(1)?))),
+        NativeFunction::new("isNaN", 1, |args| Ok(args.num(0)?.is_nan())),
+        NativeFunction::new("isInfinite", 1, |args| Ok(args.num(0)?.is_infinite())),
+    ];
+
+    let mut env = Environment::new();
+    for function in functions {
+        env.define(
+            function.name(),
+            Object::Callable(Rc::new(Box::new(function))),
+        );
     }
+    env.define("PI".to_string(), Object::Number(std::f64::consts::PI));
+    env.define("E".to_string(), Object::Number(std::f64::consts::E));
+    LoxModule::new("Math", env)
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -52,6 +221,9 @@ pub struct LoxFunction {
     body: Statement,
     env: Environment,
     is_init: bool,
+    class: Option<String>,
+    // Where it was declared, `None` for the main script
+    file: Option<Rc<SourceFile>>,
 }
 
 impl LoxFunction {
@@ -68,20 +240,31 @@ impl LoxFunction {
             body,
             env,
             is_init,
+            class: None,
+            file: None,
         }
     }
 
+    /// Marks the function as a method declared in `class`.
+    pub fn in_class(mut self, class: impl Into<String>) -> Self {
+        self.class = Some(class.into());
+        self
+    }
+
+    /// Marks the function as declared in the module `file`.
+    pub(crate) fn in_file(mut self, file: Option<Rc<SourceFile>>) -> Self {
+        self.file = file;
+        self
+    }
+
     pub fn bind(&self, instance: LoxInstance) -> LoxFunction {
         let mut env = Environment::new_with_enclosing(&self.env);
         env.define("this".to_string(), Object::Instance(instance.clone()));
 
-        LoxFunction::new(
-            self.name.clone(),
-            self.paras.clone(),
-            self.body.clone(),
+        LoxFunction {
             env,
-            self.is_init,
-        )
+            ..self.clone()
+        }
     }
 }
 
@@ -92,20 +275,18 @@ impl Callable for LoxFunction {
             env.define(param.lexeme.clone(), arg.clone());
         }
 
-        let res = interpreter.exec_block(&vec![self.body.clone()], env);
-        if let Err(LoxError::Return(o)) = &res {
-            Ok(o.clone())
-        } else if let Err(e) = res {
-            Err(e)
-        } else if self.is_init {
-            Ok(self.env.get_at(&Var {
-                identifier: Token::new(TokenType::IDENTIFIER, "this".to_string(), None, 0),
-                hops: 0,
-            })?)
-        } else {
-            res?;
-            Ok(Object::Nil)
+        let body = std::slice::from_ref(&self.body);
+        let value = match interpreter.in_file(self.file.clone(), |i| i.exec_block(body, env)) {
+            Ok(()) => Object::Nil,
+            Err(LoxError::Return(o)) => o,
+            Err(e) => return Err(e),
+        };
+
+        if self.is_init {
+            // Initializers always hand back the instance, even on `return;`
+            return self.env.get_at(&Var::new_wo_token("this", 0));
         }
+        Ok(value)
     }
     fn arity(&self) -> usize {
         self.paras.len()
@@ -113,4 +294,7 @@ impl Callable for LoxFunction {
     fn name(&self) -> String {
         self.name.lexeme.clone()
     }
+    fn class_name(&self) -> Option<String> {
+        self.class.clone()
+    }
 }
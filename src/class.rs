@@ -1,18 +1,53 @@
 use crate::callable::{Callable, LoxFunction};
+use crate::diagnostic::{Diagnostic, ErrorCode};
 use crate::interpreter::Interpreter;
 use crate::lox_error::LoxError;
+use crate::native_class::{NativeMethod, NativeProperty};
 use crate::tokens::Token;
 use crate::Object;
 
+use std::any::Any;
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::fmt;
 use std::rc::Rc;
 
-#[derive(Debug, PartialEq, Clone)]
+/// A class. Clones share the same class, and two classes are equal only
+/// if they are the same one.
+#[derive(Debug, Clone)]
 pub struct LoxClass {
+    inner: Rc<ClassData>,
+}
+
+#[derive(Debug)]
+struct ClassData {
     name: String,
-    methods: HashMap<String, LoxFunction>,
-    super_class: Option<Box<LoxClass>>,
+    methods: HashMap<String, Method>,
+    properties: HashMap<String, NativeProperty>,
+    super_class: Option<LoxClass>,
+}
+
+/// A method declared in Lox or provided by a `NativeClass`.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Method {
+    Lox(Box<LoxFunction>),
+    Native(NativeMethod),
+}
+
+impl Method {
+    pub fn arity(&self) -> usize {
+        match self {
+            Method::Lox(f) => f.arity(),
+            Method::Native(m) => m.arity(),
+        }
+    }
+
+    pub fn bind(&self, instance: LoxInstance) -> Box<dyn Callable> {
+        match self {
+            Method::Lox(f) => Box::new(f.bind(instance)),
+            Method::Native(m) => Box::new(m.bind(instance)),
+        }
+    }
 }
 
 impl LoxClass {
@@ -22,21 +57,76 @@ impl LoxClass {
         methods: HashMap<String, LoxFunction>,
     ) -> Self {
         Self {
-            name,
-            methods,
-            super_class: super_class.map(Box::new),
+            inner: Rc::new(ClassData {
+                name,
+                methods: methods
+                    .into_iter()
+                    .map(|(name, f)| (name, Method::Lox(Box::new(f))))
+                    .collect(),
+                properties: HashMap::new(),
+                super_class,
+            }),
         }
     }
 
-    pub fn find_method(&self, name: &str) -> Option<&LoxFunction> {
-        if let Some(method) = self.methods.get(name) {
+    pub(crate) fn native(
+        name: String,
+        methods: HashMap<String, NativeMethod>,
+        properties: HashMap<String, NativeProperty>,
+    ) -> Self {
+        Self {
+            inner: Rc::new(ClassData {
+                name,
+                methods: methods
+                    .into_iter()
+                    .map(|(name, m)| (name, Method::Native(m)))
+                    .collect(),
+                properties,
+                super_class: None,
+            }),
+        }
+    }
+
+    pub fn find_method(&self, name: &str) -> Option<&Method> {
+        if let Some(method) = self.inner.methods.get(name) {
             Some(method)
-        } else if let Some(superclass) = &self.super_class {
+        } else if let Some(superclass) = &self.inner.super_class {
             superclass.find_method(name)
         } else {
             None
         }
     }
+
+    /// Whether this is `other` or inherits from it.
+    pub fn is_subclass_of(&self, other: &LoxClass) -> bool {
+        self.is(other)
+            || self
+                .inner
+                .super_class
+                .as_ref()
+                .is_some_and(|superclass| superclass.is_subclass_of(other))
+    }
+
+    /// Whether both refer to the same class.
+    pub fn is(&self, other: &LoxClass) -> bool {
+        Rc::ptr_eq(&self.inner, &other.inner)
+    }
+
+    fn find_property(&self, name: &str) -> Option<&NativeProperty> {
+        if let Some(property) = self.inner.properties.get(name) {
+            Some(property)
+        } else if let Some(superclass) = &self.inner.super_class {
+            superclass.find_property(name)
+        } else {
+            None
+        }
+    }
+}
+
+impl PartialEq for LoxClass {
+    fn eq(&self, other: &Self) -> bool {
+        self.is(other)
+    }
 }
 
 impl Callable for LoxClass {
@@ -57,7 +147,7 @@ impl Callable for LoxClass {
     }
 
     fn name(&self) -> String {
-        self.name.clone()
+        self.inner.name.clone()
     }
 }
 
@@ -66,47 +156,121 @@ pub struct LoxInstance {
     inner: Rc<RefCell<InnerLoxInstance>>,
 }
 
-
 #[derive(Debug, PartialEq, Clone)]
 struct InnerLoxInstance {
     class: LoxClass,
     fields: HashMap<String, Object>,
+    native: Option<NativeData>,
+}
+
+// The Rust value behind an instance of a `NativeClass`.
+#[derive(Clone)]
+struct NativeData(Rc<RefCell<dyn Any>>);
+
+impl fmt::Debug for NativeData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<native data>")
+    }
 }
 
+impl PartialEq for NativeData {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
 
 impl LoxInstance {
     pub fn new(class: LoxClass) -> Self {
         Self {
-            inner: Rc::new(RefCell::new(
-                InnerLoxInstance {
-                    class,
-                    fields: HashMap::new(),
-                }
-            ))
+            inner: Rc::new(RefCell::new(InnerLoxInstance {
+                class,
+                fields: HashMap::new(),
+                native: None,
+            })),
         }
     }
 
     pub fn get(&self, name: &Token) -> Result<Object, LoxError> {
-        if let Some(o) = self.inner.borrow().fields.get(&name.lexeme) {
+        self.get_property(&name.lexeme)
+            .map_err(|e| e.or_span(name.span))
+    }
+
+    /// Looks `name` up as a native property, a field, then a method bound to
+    /// this instance.
+    pub fn get_property(&self, name: &str) -> Result<Object, LoxError> {
+        let property = self.inner.borrow().class.find_property(name).cloned();
+        if let Some(value) = property.and_then(|p| p.get(self)) {
+            return value;
+        }
+
+        let inner = self.inner.borrow();
+        if let Some(o) = inner.fields.get(name) {
             Ok(o.clone())
-        } else if let Some(o) = self.inner.borrow().class.find_method(&name.lexeme) {
-            Ok(Object::Callable(Rc::new(Box::new(o.bind(self.clone())))))
+        } else if let Some(method) = inner.class.find_method(name) {
+            Ok(Object::Callable(Rc::new(method.bind(self.clone()))))
         } else {
-            Err(LoxError::Error(format!(
-                "Undefined property '{}'.",
-                name.lexeme
-            )))
+            Err(LoxError::new(
+                ErrorCode::UndefinedProperty,
+                format!("Undefined property '{}'.", name),
+            ))
         }
     }
 
     pub fn set(&mut self, name: &Token, value: Object) -> Result<(), LoxError> {
-        self.inner.borrow_mut().fields.insert(name.lexeme.clone(), value);
-        Ok(())
+        self.set_property(&name.lexeme, value)
+            .map_err(|e| e.or_span(name.span))
+    }
+
+    /// Assigns through a native property if there is one, otherwise stores
+    /// a field.
+    pub fn set_property(&self, name: &str, value: Object) -> Result<(), LoxError> {
+        let property = self.inner.borrow().class.find_property(name).cloned();
+        match property.map(|p| p.set(self, &value)) {
+            Some(Some(result)) => result,
+            Some(None) => Err(Diagnostic::error(
+                ErrorCode::ReadOnlyProperty,
+                format!("Property '{}' is read-only.", name),
+            )
+            .into()),
+            None => {
+                self.set_field(name, value);
+                Ok(())
+            }
+        }
+    }
+
+    /// Stores a field, bypassing native properties.
+    pub fn set_field(&self, name: &str, value: Object) {
+        self.inner
+            .borrow_mut()
+            .fields
+            .insert(name.to_string(), value);
+    }
+
+    pub fn class(&self) -> LoxClass {
+        self.inner.borrow().class.clone()
+    }
+
+    /// Whether both refer to the same instance.
+    pub fn is(&self, other: &LoxInstance) -> bool {
+        Rc::ptr_eq(&self.inner, &other.inner)
+    }
+
+    pub(crate) fn set_native<T: 'static>(&self, value: T) {
+        self.inner.borrow_mut().native = Some(NativeData(Rc::new(RefCell::new(value))));
+    }
+
+    /// Runs `f` on the Rust value wrapped by this instance. Returns `None`
+    /// if the instance doesn't wrap a `T`.
+    pub fn with_native<T: 'static, R>(&self, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        let native = self.inner.borrow().native.clone()?;
+        let mut value = native.0.borrow_mut();
+        value.downcast_mut::<T>().map(f)
     }
 }
 
-impl ToString for LoxInstance {
-    fn to_string(&self) -> String {
-        self.inner.borrow().class.name()
+impl fmt::Display for LoxInstance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} instance", self.inner.borrow().class.name())
     }
 }
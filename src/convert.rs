@@ -0,0 +1,424 @@
+use crate::class::LoxInstance;
+use crate::diagnostic::ErrorCode;
+use crate::{LoxError, LoxList, LoxMap, Object};
+
+use std::collections::HashMap;
+
+/// Converts a Rust value into a Lox value.
+pub trait IntoLox {
+    fn into_lox(self) -> Object;
+}
+
+/// Converts a Lox value into a Rust value, failing with a type mismatch.
+pub trait FromLox: Sized {
+    fn from_lox(obj: &Object) -> Result<Self, LoxError>;
+}
+
+/// A list of arguments for a call made from Rust, such as a tuple.
+pub trait IntoLoxArgs {
+    fn into_lox_args(self) -> Vec<Object>;
+}
+
+/// A tuple of typed arguments extracted from a native call.
+pub trait FromLoxArgs: Sized {
+    fn from_lox_args(args: &[Object]) -> Result<Self, LoxError>;
+}
+
+fn mismatch(obj: &Object, expected: &str) -> LoxError {
+    LoxError::new(
+        ErrorCode::TypeMismatch,
+        format!("'{}' must be {}.", obj, expected),
+    )
+}
+
+impl IntoLox for Object {
+    fn into_lox(self) -> Object {
+        self
+    }
+}
+
+impl FromLox for Object {
+    fn from_lox(obj: &Object) -> Result<Self, LoxError> {
+        Ok(obj.clone())
+    }
+}
+
+impl IntoLox for () {
+    fn into_lox(self) -> Object {
+        Object::Nil
+    }
+}
+
+impl IntoLox for f64 {
+    fn into_lox(self) -> Object {
+        Object::Number(self)
+    }
+}
+
+impl FromLox for f64 {
+    fn from_lox(obj: &Object) -> Result<Self, LoxError> {
+        obj.get_v_num()
+    }
+}
+
+impl IntoLox for f32 {
+    fn into_lox(self) -> Object {
+        Object::Number(self.into())
+    }
+}
+
+impl FromLox for f32 {
+    fn from_lox(obj: &Object) -> Result<Self, LoxError> {
+        Ok(obj.get_v_num()? as f32)
+    }
+}
+
+// Lox only has doubles. Integers convert back only when the number is whole
+// and fits in the target type. `MAX as f64` rounds up to a power of two for
+// the 64-bit types, so the upper bound is that power of two, exclusive.
+macro_rules! integer {
+    ($($t:ty),*) => {$(
+        impl IntoLox for $t {
+            fn into_lox(self) -> Object {
+                Object::Number(self as f64)
+            }
+        }
+
+        impl FromLox for $t {
+            fn from_lox(obj: &Object) -> Result<Self, LoxError> {
+                let n = obj.get_v_num()?;
+                let end = (<$t>::MAX / 2 + 1) as f64 * 2.0;
+                if n.fract() == 0.0 && n >= <$t>::MIN as f64 && n < end {
+                    Ok(n as $t)
+                } else {
+                    Err(mismatch(obj, concat!("a whole number that fits in ", stringify!($t))))
+                }
+            }
+        }
+    )*};
+}
+
+integer!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+impl IntoLox for bool {
+    fn into_lox(self) -> Object {
+        Object::Boolean(self)
+    }
+}
+
+impl FromLox for bool {
+    fn from_lox(obj: &Object) -> Result<Self, LoxError> {
+        match obj {
+            Object::Boolean(b) => Ok(*b),
+            _ => Err(mismatch(obj, "a boolean")),
+        }
+    }
+}
+
+impl IntoLox for String {
+    fn into_lox(self) -> Object {
+        Object::String(self)
+    }
+}
+
+impl IntoLox for &str {
+    fn into_lox(self) -> Object {
+        Object::String(self.to_string())
+    }
+}
+
+impl FromLox for String {
+    fn from_lox(obj: &Object) -> Result<Self, LoxError> {
+        obj.get_v_string()
+    }
+}
+
+impl<T: IntoLox> IntoLox for Option<T> {
+    fn into_lox(self) -> Object {
+        match self {
+            Some(value) => value.into_lox(),
+            None => Object::Nil,
+        }
+    }
+}
+
+impl<T: FromLox> FromLox for Option<T> {
+    fn from_lox(obj: &Object) -> Result<Self, LoxError> {
+        match obj {
+            Object::Nil => Ok(None),
+            obj => T::from_lox(obj).map(Some),
+        }
+    }
+}
+
+impl IntoLox for LoxInstance {
+    fn into_lox(self) -> Object {
+        Object::Instance(self)
+    }
+}
+
+impl FromLox for LoxInstance {
+    fn from_lox(obj: &Object) -> Result<Self, LoxError> {
+        match obj {
+            Object::Instance(instance) => Ok(instance.clone()),
+            _ => Err(mismatch(obj, "an instance")),
+        }
+    }
+}
+
+impl IntoLox for LoxList {
+    fn into_lox(self) -> Object {
+        Object::List(self)
+    }
+}
+
+impl FromLox for LoxList {
+    fn from_lox(obj: &Object) -> Result<Self, LoxError> {
+        match obj {
+            Object::List(list) => Ok(list.clone()),
+            _ => Err(mismatch(obj, "a list")),
+        }
+    }
+}
+
+/// Copies the elements into a new Lox list.
+impl<T: IntoLox> IntoLox for Vec<T> {
+    fn into_lox(self) -> Object {
+        Object::List(LoxList::new(
+            self.into_iter().map(IntoLox::into_lox).collect(),
+        ))
+    }
+}
+
+impl<T: FromLox> FromLox for Vec<T> {
+    fn from_lox(obj: &Object) -> Result<Self, LoxError> {
+        LoxList::from_lox(obj)?
+            .to_vec()
+            .iter()
+            .map(T::from_lox)
+            .collect()
+    }
+}
+
+impl IntoLox for LoxMap {
+    fn into_lox(self) -> Object {
+        Object::Map(self)
+    }
+}
+
+impl FromLox for LoxMap {
+    fn from_lox(obj: &Object) -> Result<Self, LoxError> {
+        match obj {
+            Object::Map(map) => Ok(map.clone()),
+            _ => Err(mismatch(obj, "a map")),
+        }
+    }
+}
+
+/// Copies the entries into a new Lox map with string keys.
+impl<T: IntoLox> IntoLox for HashMap<String, T> {
+    fn into_lox(self) -> Object {
+        let map = LoxMap::new();
+        for (key, value) in self {
+            // String keys are always valid
+            let _ = map.set(Object::String(key), value.into_lox());
+        }
+        Object::Map(map)
+    }
+}
+
+impl<T: FromLox> FromLox for HashMap<String, T> {
+    fn from_lox(obj: &Object) -> Result<Self, LoxError> {
+        LoxMap::from_lox(obj)?
+            .entries()
+            .iter()
+            .map(|(key, value)| Ok((String::from_lox(key)?, T::from_lox(value)?)))
+            .collect()
+    }
+}
+
+impl IntoLoxArgs for &[Object] {
+    fn into_lox_args(self) -> Vec<Object> {
+        self.to_vec()
+    }
+}
+
+impl<const N: usize> IntoLoxArgs for &[Object; N] {
+    fn into_lox_args(self) -> Vec<Object> {
+        self.to_vec()
+    }
+}
+
+impl IntoLoxArgs for Vec<Object> {
+    fn into_lox_args(self) -> Vec<Object> {
+        self
+    }
+}
+
+macro_rules! tuple {
+    ($len:literal; $($name:ident $index:tt),*) => {
+        impl<$($name: IntoLox),*> IntoLoxArgs for ($($name,)*) {
+            #[allow(unused_variables)]
+            fn into_lox_args(self) -> Vec<Object> {
+                vec![$(self.$index.into_lox()),*]
+            }
+        }
+
+        impl<$($name: FromLox),*> FromLoxArgs for ($($name,)*) {
+            fn from_lox_args(args: &[Object]) -> Result<Self, LoxError> {
+                if args.len() != $len {
+                    return Err(LoxError::new(
+                        ErrorCode::ArityMismatch,
+                        format!("Expected {} arguments but got {}.", $len, args.len()),
+                    ));
+                }
+                Ok(($($name::from_lox(&args[$index])?,)*))
+            }
+        }
+    };
+}
+
+tuple!(0;);
+tuple!(1; A 0);
+tuple!(2; A 0, B 1);
+tuple!(3; A 0, B 1, C 2);
+tuple!(4; A 0, B 1, C 2, D 3);
+tuple!(5; A 0, B 1, C 2, D 3, E 4);
+tuple!(6; A 0, B 1, C 2, D 3, E 4, F 5);
+
+#[doc(hidden)]
+pub fn field<T: FromLox>(instance: &LoxInstance, name: &str) -> Result<T, LoxError> {
+    T::from_lox(&instance.get_property(name)?)
+}
+
+/// Implements `IntoLox` and `FromLox` for a struct by mapping the listed
+/// fields to fields of a Lox instance whose class is named after the struct.
+///
+/// ```
+/// # use rlox::{lox_struct, FromLox, IntoLox, Interpreter};
+/// #[derive(Debug, PartialEq)]
+/// struct Point {
+///     x: f64,
+///     y: f64,
+/// }
+/// lox_struct!(Point { x, y });
+///
+/// let mut lox = Interpreter::builder().build();
+/// lox.set_global("p", Point { x: 1.0, y: 2.0 });
+/// let moved = lox.eval("p.x = p.x + 1; p;").unwrap();
+/// assert_eq!(Point::from_lox(&moved).unwrap(), Point { x: 2.0, y: 2.0 });
+/// ```
+#[macro_export]
+macro_rules! lox_struct {
+    ($name:ident { $($field:ident),* $(,)? }) => {
+        impl $crate::IntoLox for $name {
+            fn into_lox(self) -> $crate::Object {
+                // One class for all values of the type, so they compare as
+                // instances of the same class
+                ::std::thread_local! {
+                    static CLASS: $crate::LoxClass = $crate::LoxClass::new(
+                        stringify!($name).to_string(),
+                        None,
+                        ::std::collections::HashMap::new(),
+                    );
+                }
+                let class = CLASS.with(|class| class.clone());
+                let instance = $crate::class::LoxInstance::new(class);
+                $(instance.set_field(stringify!($field), $crate::IntoLox::into_lox(self.$field));)*
+                $crate::Object::Instance(instance)
+            }
+        }
+
+        impl $crate::FromLox for $name {
+            fn from_lox(obj: &$crate::Object) -> Result<Self, $crate::LoxError> {
+                let instance = <$crate::class::LoxInstance as $crate::FromLox>::from_lox(obj)?;
+                Ok($name {
+                    $($field: $crate::convert::field(&instance, stringify!($field))?,)*
+                })
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        assert_eq!(f64::from_lox(&2.5.into_lox()).unwrap(), 2.5);
+        assert_eq!(u8::from_lox(&200u8.into_lox()).unwrap(), 200);
+        assert_eq!(String::from_lox(&"hi".into_lox()).unwrap(), "hi");
+        assert_eq!(Option::<bool>::from_lox(&Object::Nil).unwrap(), None);
+        assert_eq!(Some(true).into_lox(), Object::Boolean(true));
+
+        assert!(u8::from_lox(&Object::Number(256.0)).is_err());
+        assert!(i32::from_lox(&Object::Number(1.5)).is_err());
+        assert!(bool::from_lox(&Object::Nil).is_err());
+
+        assert!(i64::from_lox(&Object::Number(2f64.powi(63))).is_err());
+        assert!(u64::from_lox(&Object::Number(2f64.powi(64))).is_err());
+        assert!(usize::from_lox(&Object::Number(2f64.powi(64))).is_err());
+        assert!(i64::from_lox(&Object::Number(-2f64.powi(63) * 2.0)).is_err());
+        assert_eq!(
+            i64::from_lox(&Object::Number(-2f64.powi(63))).unwrap(),
+            i64::MIN
+        );
+        let big = 2f64.powi(63) - 1024.0;
+        assert_eq!(i64::from_lox(&Object::Number(big)).unwrap(), big as i64);
+        assert_eq!(u8::from_lox(&Object::Number(255.0)).unwrap(), 255);
+
+        let list = vec![1, 2].into_lox();
+        assert_eq!(list.to_string(), "[1, 2]");
+        assert_eq!(Vec::<u8>::from_lox(&list).unwrap(), vec![1, 2]);
+        assert!(Vec::<String>::from_lox(&list).is_err());
+
+        let map = HashMap::from([("a".to_string(), 1.0)]);
+        assert_eq!(
+            HashMap::<String, f64>::from_lox(&map.clone().into_lox()).unwrap(),
+            map
+        );
+    }
+
+    #[test]
+    fn tuples() {
+        let args = (1, "a", None::<f64>).into_lox_args();
+        assert_eq!(
+            args,
+            vec![
+                Object::Number(1.0),
+                Object::String("a".to_string()),
+                Object::Nil
+            ]
+        );
+
+        let (n, s, o) = <(i32, String, Option<f64>)>::from_lox_args(&args).unwrap();
+        assert_eq!((n, s.as_str(), o), (1, "a", None));
+        assert!(<(i32,)>::from_lox_args(&args).is_err());
+    }
+
+    struct Point {
+        x: f64,
+        y: f64,
+    }
+    lox_struct!(Point { x, y });
+
+    struct Size {
+        x: f64,
+        y: f64,
+    }
+    lox_struct!(Size { x, y });
+
+    #[test]
+    fn lox_struct_class_per_type() {
+        let class = |value: Object| match value {
+            Object::Instance(instance) => instance.class(),
+            _ => panic!("expected an instance"),
+        };
+        let a = class(Point { x: 1.0, y: 2.0 }.into_lox());
+        let b = class(Point { x: 3.0, y: 4.0 }.into_lox());
+        let c = class(Size { x: 1.0, y: 2.0 }.into_lox());
+        assert!(a.is(&b));
+        assert!(!a.is(&c));
+    }
+}
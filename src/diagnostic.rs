@@ -0,0 +1,367 @@
+use crate::span::Span;
+use std::fmt::{self, Write};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Note => write!(f, "note"),
+        }
+    }
+}
+
+/// Stable identifiers for every diagnostic rlox can produce.
+///
+/// Codes are grouped by phase: `E00xx` scanner, `E01xx` parser,
+/// `E02xx` resolver and `E03xx` runtime. A code is never reused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    UnexpectedCharacter,
+    UnterminatedString,
+
+    ExpectedToken,
+    ExpectedExpression,
+    InvalidAssignmentTarget,
+    TooManyParameters,
+    TooManyArguments,
+    TooMuchNesting,
+
+    ReturnFromInitializer,
+    SelfInheritance,
+    ThisOutsideClass,
+    LocalInOwnInitializer,
+    TopLevelReturn,
+    DuplicateDeclaration,
+    SuperOutsideClass,
+    SuperWithoutSuperclass,
+    OutsideLoop,
+
+    OperandMustBeNumber,
+    OperandsMustBeNumbers,
+    OperandsMustBeNumbersOrStrings,
+    UndefinedVariable,
+    UndefinedProperty,
+    NotCallable,
+    ArityMismatch,
+    NotAnInstance,
+    SuperclassNotClass,
+    TypeMismatch,
+    Io,
+    Native,
+    UninitializedNative,
+    ReadOnlyProperty,
+    IndexOutOfRange,
+    NotIndexable,
+    UndefinedKey,
+    Uncaught,
+    ModuleNotFound,
+    CircularImport,
+    InvalidModule,
+    DivisionByZero,
+    StackOverflow,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::UnexpectedCharacter => "E0001",
+            ErrorCode::UnterminatedString => "E0002",
+
+            ErrorCode::ExpectedToken => "E0101",
+            ErrorCode::ExpectedExpression => "E0102",
+            ErrorCode::InvalidAssignmentTarget => "E0103",
+            ErrorCode::TooManyParameters => "E0104",
+            ErrorCode::TooManyArguments => "E0105",
+            ErrorCode::TooMuchNesting => "E0106",
+
+            ErrorCode::ReturnFromInitializer => "E0201",
+            ErrorCode::SelfInheritance => "E0202",
+            ErrorCode::ThisOutsideClass => "E0203",
+            ErrorCode::LocalInOwnInitializer => "E0204",
+            ErrorCode::TopLevelReturn => "E0205",
+            ErrorCode::DuplicateDeclaration => "E0206",
+            ErrorCode::SuperOutsideClass => "E0207",
+            ErrorCode::SuperWithoutSuperclass => "E0208",
+            ErrorCode::OutsideLoop => "E0209",
+
+            ErrorCode::OperandMustBeNumber => "E0301",
+            ErrorCode::OperandsMustBeNumbers => "E0302",
+            ErrorCode::OperandsMustBeNumbersOrStrings => "E0303",
+            ErrorCode::UndefinedVariable => "E0304",
+            ErrorCode::UndefinedProperty => "E0305",
+            ErrorCode::NotCallable => "E0306",
+            ErrorCode::ArityMismatch => "E0307",
+            ErrorCode::NotAnInstance => "E0308",
+            ErrorCode::SuperclassNotClass => "E0309",
+            ErrorCode::TypeMismatch => "E0310",
+            ErrorCode::Io => "E0311",
+            ErrorCode::Native => "E0312",
+            ErrorCode::UninitializedNative => "E0313",
+            ErrorCode::ReadOnlyProperty => "E0314",
+            ErrorCode::IndexOutOfRange => "E0315",
+            ErrorCode::NotIndexable => "E0316",
+            ErrorCode::UndefinedKey => "E0317",
+            ErrorCode::Uncaught => "E0318",
+            ErrorCode::ModuleNotFound => "E0319",
+            ErrorCode::CircularImport => "E0320",
+            ErrorCode::InvalidModule => "E0321",
+            ErrorCode::DivisionByZero => "E0322",
+            ErrorCode::StackOverflow => "E0323",
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suggestion {
+    pub span: Span,
+    pub message: String,
+    pub replacement: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub code: ErrorCode,
+    pub severity: Severity,
+    pub message: String,
+    pub primary: Option<Label>,
+    pub secondary: Vec<Label>,
+    pub notes: Vec<String>,
+    pub suggestions: Vec<Suggestion>,
+}
+
+impl Diagnostic {
+    pub fn new<T>(code: ErrorCode, severity: Severity, message: T) -> Self
+    where
+        T: Into<String>,
+    {
+        Self {
+            code,
+            severity,
+            message: message.into(),
+            primary: None,
+            secondary: Vec::new(),
+            notes: Vec::new(),
+            suggestions: Vec::new(),
+        }
+    }
+
+    pub fn error<T>(code: ErrorCode, message: T) -> Self
+    where
+        T: Into<String>,
+    {
+        Self::new(code, Severity::Error, message)
+    }
+
+    pub fn with_span(self, span: Span) -> Self {
+        self.with_span_label(span, "")
+    }
+
+    pub fn with_span_label<T>(mut self, span: Span, message: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.primary = Some(Label {
+            span,
+            message: message.into(),
+        });
+        self
+    }
+
+    /// Sets the primary span unless the diagnostic already has one.
+    pub fn or_span(self, span: Span) -> Self {
+        if self.primary.is_some() {
+            self
+        } else {
+            self.with_span(span)
+        }
+    }
+
+    pub fn with_label<T>(mut self, span: Span, message: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.secondary.push(Label {
+            span,
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn with_note<T>(mut self, note: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_suggestion<T, U>(mut self, span: Span, message: T, replacement: U) -> Self
+    where
+        T: Into<String>,
+        U: Into<String>,
+    {
+        self.suggestions.push(Suggestion {
+            span,
+            message: message.into(),
+            replacement: replacement.into(),
+        });
+        self
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        self.primary.as_ref().map(|l| l.span)
+    }
+
+    /// Renders the diagnostic rustc-style with the offending source lines.
+    pub fn render(&self, file_name: &str, source: &str) -> String {
+        let lines: Vec<&str> = source.lines().collect();
+        let on_a_line = |l: &&Label| l.span.line >= 1 && l.span.line <= lines.len();
+
+        let mut labels: Vec<(&Label, bool)> = self
+            .primary
+            .iter()
+            .filter(on_a_line)
+            .map(|l| (l, true))
+            .chain(self.secondary.iter().filter(on_a_line).map(|l| (l, false)))
+            .collect();
+        labels.sort_by_key(|(l, _)| (l.span.line, l.span.column));
+
+        let gutter = labels
+            .iter()
+            .map(|(l, _)| l.span.line.to_string().len())
+            .max()
+            .unwrap_or(1);
+        let pad = " ".repeat(gutter);
+
+        let mut out = String::new();
+        let _ = writeln!(out, "{}[{}]: {}", self.severity, self.code, self.message);
+
+        let location = self
+            .primary
+            .as_ref()
+            .map(|l| l.span)
+            .filter(|s| s.line >= 1);
+        if let Some(span) = location {
+            let _ = writeln!(
+                out,
+                "{}--> {}:{}:{}",
+                pad, file_name, span.line, span.column
+            );
+        }
+
+        if !labels.is_empty() {
+            let _ = writeln!(out, "{} |", pad);
+            let mut last_line = None;
+            for (label, is_primary) in labels {
+                let line = label.span.line;
+                let text = lines[line - 1];
+                if last_line != Some(line) {
+                    if matches!(last_line, Some(last) if line > last + 1) {
+                        let _ = writeln!(out, "...");
+                    }
+                    let _ = writeln!(out, "{:>w$} | {}", line, text, w = gutter);
+                    last_line = Some(line);
+                }
+
+                let indent: String = text
+                    .chars()
+                    .take(label.span.column - 1)
+                    .map(|c| if c == '\t' { '\t' } else { ' ' })
+                    .collect();
+                let width = Self::marker_width(label.span, source, text);
+                let marker = if is_primary { "^" } else { "-" }.repeat(width);
+                let marker_line = format!("{} | {}{} {}", pad, indent, marker, label.message);
+                let _ = writeln!(out, "{}", marker_line.trim_end());
+            }
+            let _ = writeln!(out, "{} |", pad);
+        }
+
+        for note in &self.notes {
+            let _ = writeln!(out, "{} = note: {}", pad, note);
+        }
+        for suggestion in &self.suggestions {
+            let _ = writeln!(
+                out,
+                "{} = help: {}: `{}`",
+                pad, suggestion.message, suggestion.replacement
+            );
+        }
+
+        out
+    }
+
+    // Number of characters of `span` on its first line, at least one.
+    fn marker_width(span: Span, source: &str, line: &str) -> usize {
+        let available = line.chars().count().saturating_sub(span.column - 1);
+        let width = source
+            .get(span.start..span.end.min(source.len()))
+            .map(|s| s.chars().take_while(|c| *c != '\n').count())
+            .unwrap_or(0);
+        width.min(available).max(1)
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}[{}]: {}", self.severity, self.code, self.message)?;
+        if let Some(span) = self.span().filter(|s| s.line >= 1) {
+            write!(f, " at {}", span)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_snippet() {
+        let source = "var a = 1;\nprint a +;\n";
+        let diagnostic = Diagnostic::error(ErrorCode::ExpectedExpression, "Expect expression.")
+            .with_span_label(Span::new(20, 21, 2, 10), "expected an expression")
+            .with_label(Span::new(6, 7, 2, 7), "left operand")
+            .with_note("binary operators need two operands");
+
+        let expected = "\
+error[E0102]: Expect expression.
+ --> test.lox:2:10
+  |
+2 | print a +;
+  |       - left operand
+  |          ^ expected an expression
+  |
+  = note: binary operators need two operands
+";
+        assert_eq!(diagnostic.render("test.lox", source), expected);
+    }
+
+    #[test]
+    fn render_without_span() {
+        let diagnostic = Diagnostic::error(ErrorCode::UndefinedVariable, "Undefined variable 'x'.");
+        assert_eq!(
+            diagnostic.render("test.lox", "print x;"),
+            "error[E0304]: Undefined variable 'x'.\n"
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use crate::diagnostic::{Diagnostic, ErrorCode};
 use crate::expressions::Var;
 use crate::{object::Object, LoxError};
 use std::cell::RefCell;
@@ -13,6 +14,8 @@ pub struct Environment {
 struct Env {
     values: HashMap<String, Object>,
     enclosing: Option<Environment>,
+    // Ends the walk to the global scope, see `new_global`
+    global: bool,
 }
 
 impl Environment {
@@ -21,6 +24,7 @@ impl Environment {
             inner: Rc::new(RefCell::new(Env {
                 values: HashMap::new(),
                 enclosing: None,
+                global: true,
             })),
         }
     }
@@ -30,15 +34,24 @@ impl Environment {
             inner: Rc::new(RefCell::new(Env {
                 values: HashMap::new(),
                 enclosing: Some(enclosing.clone()),
+                global: false,
             })),
         }
     }
 
+    /// A global scope of its own, as used by a module. Unresolved names
+    /// are defined here, and looked up here before in `enclosing`.
+    pub fn new_global(enclosing: &Environment) -> Environment {
+        let env = Self::new_with_enclosing(enclosing);
+        env.inner.borrow_mut().global = true;
+        env
+    }
+
     // Debug
     pub fn get_keys(&self) -> Vec<String> {
         let env = self.inner.borrow();
 
-        let mut v : Vec<_> = env.values.keys().map(|x| x.clone()).collect();
+        let mut v: Vec<_> = env.values.keys().cloned().collect();
         if let Some(env) = env.enclosing.clone() {
             v.append(&mut env.get_keys());
         }
@@ -49,16 +62,21 @@ impl Environment {
         self.inner.borrow_mut().values.insert(name, value);
     }
 
+    /// Looks `name` up in this scope only.
+    pub fn get_local(&self, name: &str) -> Option<Object> {
+        self.inner.borrow().values.get(name).cloned()
+    }
+
     pub fn get(&self, name: &str) -> Result<Object, LoxError> {
         if let Some(v) = self.inner.borrow().values.get(name) {
             Ok(v.clone())
         } else if let Some(env) = self.inner.borrow().enclosing.as_ref() {
             env.get(name)
         } else {
-            Err(LoxError::UndefinedVariable(format!(
-                "Undefined variable '{}' in env with keys {:?}.",
-                name, self.inner.borrow().values.keys()
-            )))
+            Err(LoxError::new(
+                ErrorCode::UndefinedVariable,
+                format!("Undefined variable '{}'.", name),
+            ))
         }
     }
 
@@ -69,34 +87,62 @@ impl Environment {
         } else if let Some(env) = self.inner.borrow_mut().enclosing.as_mut() {
             env.assign(name, value)
         } else {
-            Err(LoxError::UndefinedVariable(format!(
-                "Undefined variable '{}'.",
-                name
-            )))
+            Err(LoxError::new(
+                ErrorCode::UndefinedVariable,
+                format!("Undefined variable '{}'.", name),
+            ))
         }
     }
 
-    fn ancestor(&self, distance: usize) -> Result<Environment, LoxError> {
-        if distance == 0 {
-            Ok(self.clone())
-        } else {
-            self.inner
-                .borrow()
-                .enclosing
-                .as_ref()
-                .unwrap()
-                .ancestor(distance - 1)
+    // `None` walks up to the innermost global environment.
+    fn ancestor(&self, distance: Option<usize>) -> Environment {
+        let mut env = self.clone();
+        let mut hops = 0;
+        while distance != Some(hops) {
+            if distance.is_none() && env.inner.borrow().global {
+                break;
+            }
+            let enclosing = env.inner.borrow().enclosing.clone();
+            match enclosing {
+                Some(enclosing) => env = enclosing,
+                None => break,
+            }
+            hops += 1;
         }
+        env
     }
 
     pub fn assign_at(&mut self, var: &Var, value: Object) -> Result<(), LoxError> {
-        let mut env = self.ancestor(var.hops)?;
+        let mut env = self.ancestor(var.hops);
+        if var.hops.is_none() {
+            return env.assign_global(var.name(), value);
+        }
         env.assign(var.name().to_string(), value)?;
         Ok(())
     }
 
+    // Assigns in this global scope only, so that a module can't change the
+    // built-ins every other module sees.
+    fn assign_global(&self, name: &str, value: Object) -> Result<(), LoxError> {
+        let mut env = self.inner.borrow_mut();
+        if let Some(slot) = env.values.get_mut(name) {
+            *slot = value;
+            return Ok(());
+        }
+        let mut err = Diagnostic::error(
+            ErrorCode::UndefinedVariable,
+            format!("Undefined variable '{}'.", name),
+        );
+        if env.enclosing.as_ref().is_some_and(|e| e.get(name).is_ok()) {
+            err = err.with_note(
+                "built-ins can't be assigned to, declare a global with 'var' to shadow one",
+            );
+        }
+        Err(err.into())
+    }
+
     pub fn get_at(&self, var: &Var) -> Result<Object, LoxError> {
-        let env = self.ancestor(var.hops)?;
+        let env = self.ancestor(var.hops);
         env.get(var.name())
     }
 }
@@ -110,6 +156,8 @@ impl Default for Environment {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::span::Span;
+    use crate::tokens::{Token, TokenType};
 
     #[test]
     fn env_define() {
@@ -138,7 +186,7 @@ mod tests {
     fn env_get_at() {
         let mut env = Environment::new();
         env.define("x".to_string(), Object::Number(10.0));
-        let mut env = Environment::new_with_enclosing(&env);
+        let env = Environment::new_with_enclosing(&env);
 
         assert_eq!(
             env.get_at(&Var::new_wo_token("x", 1)).unwrap(),
@@ -160,4 +208,26 @@ mod tests {
             Object::Number(100.0)
         );
     }
+
+    #[test]
+    fn globals_stop_at_new_global() {
+        let mut outer = Environment::new();
+        outer.define("x".to_string(), Object::Number(1.0));
+        outer.define("y".to_string(), Object::Number(2.0));
+        let mut module = Environment::new_global(&outer);
+        module.define("x".to_string(), Object::Number(10.0));
+        let local = Environment::new_with_enclosing(&module);
+
+        let global = |name| {
+            Var::new(Token::new(
+                TokenType::IDENTIFIER,
+                name,
+                None,
+                Span::default(),
+            ))
+        };
+        assert_eq!(local.get_at(&global("x")).unwrap(), Object::Number(10.0));
+        assert_eq!(local.get_at(&global("y")).unwrap(), Object::Number(2.0));
+        assert_eq!(module.get_local("y"), None);
+    }
 }
@@ -1,33 +1,67 @@
 use crate::object::Object;
+use crate::span::Span;
+use crate::statements::Statement;
 use crate::tokens::{Token, TokenType};
 
 #[derive(Debug, Clone, PartialEq)]
-pub enum Expr {
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind {
     Binary(Box<Expr>, Token, Box<Expr>),
     Grouping(Box<Expr>),
     Literal(Object),
     Unary(Token, Box<Expr>),
     Variable(Var),
     Assignment(Var, Box<Expr>),
+    /// `and`, `or` and `??`
     Logical(Box<Expr>, Token, Box<Expr>),
+    /// `condition ? then : else`
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
     Call(Box<Expr>, Vec<Expr>),
     Get(Box<Expr>, Token),
     Set(Box<Expr>, Token, Box<Expr>),
     This(Var),
     Super(Var, Token),
+    /// An anonymous function, named by a synthetic `lambda` token.
+    Lambda(Token, Vec<Token>, Box<Statement>),
+    List(Vec<Expr>),
+    Map(Vec<(Expr, Expr)>),
+    Index(Box<Expr>, Token, Box<Expr>),
+    /// A chain of calls, properties and indexes containing `?.`. It
+    /// evaluates to nil as soon as an `Optional` receiver in it is nil.
+    Chain(Box<Expr>),
+    /// The receiver left of a `?.`, only found inside a `Chain`.
+    Optional(Box<Expr>),
+    SetIndex(Box<Expr>, Token, Box<Expr>, Box<Expr>),
+    /// `target op= value`, or `++target` and `target++` with a value of 1.
+    /// The target is a `Variable`, `Get` or `Index` whose receiver is only
+    /// evaluated once, and the token is the binary operator applied. A
+    /// postfix update evaluates to the old value.
+    Update(Box<Expr>, Token, Box<Expr>, bool),
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Var {
     pub identifier: Token,
-    pub hops: usize,
+    /// Distance to the scope declaring the variable, `None` for globals.
+    pub hops: Option<usize>,
 }
 
 impl Var {
     pub fn new(identifier: Token) -> Self {
         Self {
             identifier,
-            hops: 0,
+            hops: None,
         }
     }
     pub fn name(&self) -> &str {
@@ -36,8 +70,8 @@ impl Var {
 
     pub fn new_wo_token(name: &str, hops: usize) -> Self {
         Self {
-            identifier: Token::new(TokenType::IDENTIFIER, name, None, 0),
-            hops,
+            identifier: Token::new(TokenType::IDENTIFIER, name, None, Span::default()),
+            hops: Some(hops),
         }
     }
 }
@@ -1,74 +1,290 @@
+use crate::diagnostic::{Diagnostic, ErrorCode};
 use crate::environment::Environment;
-use crate::expressions::Var;
+use crate::expressions::{ExprKind, Var};
+use crate::statements::{Imports, StatementKind};
 use crate::{
-    callable::{Callable, Clock, LoxFunction},
-    class::LoxClass,
+    callable::{self, Args, Callable, LoxFunction, NativeFunction},
+    class::{LoxClass, LoxInstance},
+    convert::{IntoLox, IntoLoxArgs},
+    list::LoxList,
+    lox_error::{EvalError, Frame, SourceFile, Thrown},
+    map::LoxMap,
+    module::LoxModule,
+    native_class::{self, NativeClass},
     object::Object,
-    resolver::Resolver,
+    span::Span,
+    string,
     tokens::TokenType,
-    Expr, LoxError, Statement, Token,
+    Expr, LoxError, Parser, Resolver, RuntimeError, Scanner, Statement, Token,
 };
 use std::collections::{HashMap, VecDeque};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
 use std::mem;
-use std::process::exit;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
-#[derive(Debug, Clone)]
+// Calls nested deeper than this fail with "Stack overflow.", as in clox.
+const MAX_FRAMES: usize = 64;
+// How much stack the calls below the outermost one may use. A call's
+// share depends on the function's body and on the build, so past this
+// calls fail the same way even before `MAX_FRAMES` is reached. It leaves
+// room to spare on a 2 MiB thread, the default for spawned threads.
+const MAX_STACK: usize = 1 << 20;
+
+/// A long-lived interpreter. Globals defined by one `eval` stay visible to
+/// the next, so a host can load a script once and call into it many times.
 pub struct Interpreter {
-    statements: Vec<Statement>,
-    current: usize,
+    // Natives and built-in classes, enclosing the globals of the main
+    // script and of every module
+    builtins: Environment,
+    globals: Environment,
     env: Environment,
+    frames: Vec<Frame>,
+    // Where the stack was at the outermost call, see `MAX_STACK`
+    stack_base: usize,
+    // What runtime errors become when caught
+    error_class: LoxClass,
+    // Imported modules by canonical path, and the files being run, the
+    // innermost last
+    modules: HashMap<PathBuf, LoxModule>,
+    loading: Vec<PathBuf>,
+    // The module whose code is running, `None` for the main script
+    file: Option<Rc<SourceFile>>,
+    stdout: Box<dyn Write>,
+    stderr: Box<dyn Write>,
 }
 
-impl Interpreter {
-    fn new(statements: Vec<Statement>) -> Self {
-        let mut env = Environment::new();
-        let clock = Clock {};
-        env.define(clock.name(), Object::Callable(Rc::new(Box::new(clock))));
-        Interpreter {
-            statements,
-            current: 0,
-            env,
+impl fmt::Debug for Interpreter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Interpreter")
+            .field("globals", &self.globals)
+            .field("frames", &self.frames)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Default)]
+pub struct InterpreterBuilder {
+    globals: Vec<(String, Object)>,
+    stdout: Option<Box<dyn Write>>,
+    stderr: Option<Box<dyn Write>>,
+}
+
+impl InterpreterBuilder {
+    /// Defines a global before any script runs.
+    pub fn global(mut self, name: impl Into<String>, value: impl IntoLox) -> Self {
+        self.globals.push((name.into(), value.into_lox()));
+        self
+    }
+
+    /// Where `print` writes to. Defaults to the process's stdout.
+    pub fn stdout(mut self, out: impl Write + 'static) -> Self {
+        self.stdout = Some(Box::new(out));
+        self
+    }
+
+    /// Where `Interpreter::report` writes diagnostics to. Defaults to the
+    /// process's stderr.
+    pub fn stderr(mut self, err: impl Write + 'static) -> Self {
+        self.stderr = Some(Box::new(err));
+        self
+    }
+
+    pub fn build(self) -> Interpreter {
+        let builtins = Environment::new();
+        let globals = Environment::new_global(&builtins);
+        let mut interpreter = Interpreter {
+            env: globals.clone(),
+            builtins,
+            globals,
+            frames: Vec::new(),
+            stack_base: 0,
+            error_class: native_class::error_class(),
+            modules: HashMap::new(),
+            loading: Vec::new(),
+            file: None,
+            stdout: self.stdout.unwrap_or_else(|| Box::new(io::stdout())),
+            stderr: self.stderr.unwrap_or_else(|| Box::new(io::stderr())),
+        };
+
+        interpreter.register(callable::clock());
+        interpreter.register(string::str());
+        interpreter.register(string::num());
+        interpreter.define_builtin("Math", Object::Module(callable::math()));
+        let error_class = interpreter.error_class.clone();
+        interpreter.define_builtin("Error", Object::Class(error_class));
+        for (name, value) in self.globals {
+            interpreter.set_global(name, value);
         }
+        interpreter
     }
+}
 
-    pub fn interpret(statements: Vec<Statement>) -> Result<Object, LoxError> {
-        let mut interpreter = Self::new(statements);
-        Resolver::run(&mut interpreter.statements);
-        interpreter.run()
+impl Interpreter {
+    pub fn builder() -> InterpreterBuilder {
+        InterpreterBuilder::default()
     }
 
-    pub fn run(&mut self) -> Result<Object, LoxError> {
-        while !self.is_at_end() {
-            let ret = self.eval_stmt(self.peek());
-            match ret {
-                Err(LoxError::Return(x)) => return Ok(x),
-                Err(e) => {
-                    println!("Error: {:?}", e);
-                    exit(20);
-                }
-                Ok(_) => {
-                    self.advance()?;
-                }
+    /// Scans, parses and resolves `source`. Scanner errors don't stop the
+    /// parser, so both get reported together; only a syntactically valid
+    /// program is resolved.
+    pub fn compile(source: &str) -> Result<Vec<Statement>, Vec<Diagnostic>> {
+        let mut scanner = Scanner::new(source.to_string());
+        let mut errors = scanner.scan_tokens().err().unwrap_or_default();
+
+        let mut statements = match Parser::new(scanner.get_tokens()).parse() {
+            Ok(statements) if errors.is_empty() => statements,
+            Ok(_) => return Err(errors),
+            Err(mut parse_errors) => {
+                errors.append(&mut parse_errors);
+                return Err(errors);
             }
+        };
+
+        Resolver::run(&mut statements)?;
+        Ok(statements)
+    }
+
+    /// Compiles and runs `source` against this interpreter's globals.
+    ///
+    /// Returns the value of the last statement when it is an expression
+    /// statement, and `nil` otherwise.
+    pub fn eval(&mut self, source: &str) -> Result<Object, EvalError> {
+        let statements = Self::compile(source)?;
+        Ok(self.interpret(statements)?)
+    }
+
+    /// Like `eval`, for the contents of the file at `path`. Its imports are
+    /// found relative to the file's directory.
+    pub fn eval_script(
+        &mut self,
+        path: impl AsRef<Path>,
+        source: &str,
+    ) -> Result<Object, EvalError> {
+        let path = path.as_ref();
+        self.loading
+            .push(fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()));
+        let result = self.eval(source);
+        self.loading.pop();
+        result
+    }
+
+    /// Runs statements that already went through `Resolver::run`.
+    pub fn interpret(&mut self, statements: Vec<Statement>) -> Result<Object, RuntimeError> {
+        let mut value = Object::Nil;
+        for stmt in statements {
+            value = match stmt.kind {
+                StatementKind::Expr(e) => self.eval_expr(&e)?,
+                kind => {
+                    self.eval_stmt(Statement::new(kind, stmt.span))?;
+                    Object::Nil
+                }
+            };
         }
-        Ok(Object::Nil)
+        Ok(value)
+    }
+
+    /// Renders `err` for the source it came from to the configured stderr.
+    pub fn report(&mut self, file_name: &str, source: &str, err: &EvalError) -> io::Result<()> {
+        write!(self.stderr, "{}", err.render(file_name, source))?;
+        self.stderr.flush()
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<Object> {
+        self.globals.get(name).ok()
+    }
+
+    /// Defines `name` in the global scope, replacing any previous value.
+    pub fn set_global(&mut self, name: impl Into<String>, value: impl IntoLox) {
+        self.globals.define(name.into(), value.into_lox());
+    }
+
+    /// Exposes a Rust closure to scripts as the global function `name`.
+    ///
+    /// ```
+    /// # use rlox::{Interpreter, Object};
+    /// let mut lox = Interpreter::builder().build();
+    /// lox.define_native("sqrt", 1, |args| Ok(Object::Number(args.num(0)?.sqrt())));
+    /// assert_eq!(lox.eval("sqrt(16);").unwrap(), Object::Number(4.0));
+    /// ```
+    pub fn define_native<F, R>(&mut self, name: &str, arity: usize, function: F)
+    where
+        F: Fn(&Args) -> Result<R, LoxError> + 'static,
+        R: IntoLox,
+    {
+        self.register(NativeFunction::new(name, arity, function));
+    }
+
+    /// Like `define_native`, but the function accepts `min_arity` or more
+    /// arguments.
+    pub fn define_native_variadic<F, R>(&mut self, name: &str, min_arity: usize, function: F)
+    where
+        F: Fn(&Args) -> Result<R, LoxError> + 'static,
+        R: IntoLox,
+    {
+        self.register(NativeFunction::variadic(name, min_arity, function));
+    }
+
+    /// Calls a function or class with the same checks as a call in a script.
+    ///
+    /// Arguments can be a slice of `Object`s or a tuple of `IntoLox` values.
+    pub fn call(
+        &mut self,
+        callee: &Object,
+        args: impl IntoLoxArgs,
+    ) -> Result<Object, RuntimeError> {
+        Ok(self.call_value(callee, &args.into_lox_args(), Span::default())?)
+    }
+
+    /// Looks up `name` on `instance`, binding `this`, and calls it.
+    pub fn call_method(
+        &mut self,
+        instance: &LoxInstance,
+        name: &str,
+        args: impl IntoLoxArgs,
+    ) -> Result<Object, RuntimeError> {
+        let method = instance.get_property(name)?;
+        self.call(&method, args)
+    }
+
+    /// Exposes a `NativeClass` to scripts, and to the modules they import,
+    /// as a global class.
+    pub fn define_class<T: 'static>(&mut self, class: NativeClass<T>) {
+        let class = class.build();
+        self.define_builtin(class.name(), Object::Class(class));
+    }
+
+    fn register(&mut self, native: NativeFunction) {
+        self.define_builtin(native.name(), Object::Callable(Rc::new(Box::new(native))));
+    }
+
+    // Unlike `set_global`, visible to modules too
+    fn define_builtin(&mut self, name: impl Into<String>, value: Object) {
+        self.builtins.define(name.into(), value);
     }
 
     fn eval_stmt(&mut self, stmt: Statement) -> Result<(), LoxError> {
-        match stmt {
-            Statement::Expr(e) => {
+        match stmt.kind {
+            StatementKind::Expr(e) => {
                 let _ = self.eval_expr(&e)?;
                 Ok(())
             }
-            Statement::Print(e) => self.eval_print(e),
-            Statement::VarDecl(t, e) => self.var_dec(t, e),
-            Statement::Block(stms) => self.block(stms),
-            Statement::If(cond, then_stm, else_stm) => self.if_stm(cond, *then_stm, else_stm),
-            Statement::While(cond, stm) => self.while_stm(cond, *stm),
-            Statement::FuncDecl(name, args, stm) => self.function_decl(name, args, *stm),
-            Statement::Return(e) => self.return_stm(e),
-            Statement::ClassDecl(name, superclass, methods) => {
+            StatementKind::Print(e) => self.eval_print(e),
+            StatementKind::VarDecl(t, e) => self.var_dec(t, e),
+            StatementKind::Block(stms) => self.block(stms),
+            StatementKind::If(cond, then_stm, else_stm) => self.if_stm(cond, *then_stm, else_stm),
+            StatementKind::While(cond, stm, increment) => self.while_stm(cond, *stm, increment),
+            StatementKind::FuncDecl(name, args, stm) => self.function_decl(name, args, *stm),
+            StatementKind::Return(e) => self.return_stm(e),
+            StatementKind::Break => Err(LoxError::Break),
+            StatementKind::Continue => Err(LoxError::Continue),
+            StatementKind::Import(path, imports) => self.import_stm(&path, imports),
+            StatementKind::Throw(e) => self.throw_stm(e, stmt.span),
+            StatementKind::Try(body, catch, finally) => self.try_stm(*body, catch, finally),
+            StatementKind::ClassDecl(name, superclass, methods) => {
                 self.class_decl(name, superclass, methods)
             }
         }
@@ -80,7 +296,8 @@ impl Interpreter {
         args: Vec<Token>,
         stm: Statement,
     ) -> Result<(), LoxError> {
-        let f = LoxFunction::new(name.clone(), args, stm, self.env.clone(), false);
+        let f = LoxFunction::new(name.clone(), args, stm, self.env.clone(), false)
+            .in_file(self.file.clone());
 
         self.env
             .define(name.lexeme, Object::Callable(Rc::new(Box::new(f))));
@@ -96,10 +313,19 @@ impl Interpreter {
     ) -> Result<(), LoxError> {
         let superclass = {
             if let Some(superclass) = superclass {
-                let superclass = self.eval_expr(&Expr::Variable(superclass))?;
+                let span = superclass.identifier.span;
+                let superclass =
+                    self.eval_expr(&Expr::new(ExprKind::Variable(superclass), span))?;
                 match superclass {
                     Object::Class(x) => Some(x),
-                    _ => return Err(LoxError::Error("Superclass must be a class".to_string())),
+                    _ => {
+                        return Err(Diagnostic::error(
+                            ErrorCode::SuperclassNotClass,
+                            "Superclass must be a class.",
+                        )
+                        .with_span(span)
+                        .into())
+                    }
                 }
             } else {
                 None
@@ -119,17 +345,20 @@ impl Interpreter {
             }
         };
 
+        let class_name = name.lexeme.clone();
         let mut method_map = HashMap::with_capacity(methods.len());
 
         for method in methods {
-            if let Statement::FuncDecl(name, args, body) = method {
+            if let StatementKind::FuncDecl(name, args, body) = method.kind {
                 let f = LoxFunction::new(
                     name.clone(),
                     args,
                     *body,
                     self.env.clone(),
                     name.to_string() == "init",
-                );
+                )
+                .in_class(&class_name)
+                .in_file(self.file.clone());
                 method_map.insert(name.lexeme, f);
             } else {
                 panic!("error class_decl");
@@ -146,18 +375,225 @@ impl Interpreter {
         Ok(())
     }
 
-    fn while_stm(&mut self, cond: Expr, stm: Statement) -> Result<(), LoxError> {
+    fn while_stm(
+        &mut self,
+        cond: Expr,
+        stm: Statement,
+        increment: Option<Box<Expr>>,
+    ) -> Result<(), LoxError> {
         while (self.eval_expr(&cond)?).is_truthy() {
-            self.eval_stmt(stm.clone())?;
+            match self.eval_stmt(stm.clone()) {
+                Ok(()) | Err(LoxError::Continue) => (),
+                Err(LoxError::Break) => break,
+                Err(e) => return Err(e),
+            }
+            if let Some(increment) = &increment {
+                self.eval_expr(increment)?;
+            }
         }
         Ok(())
     }
 
-    fn return_stm(&mut self, e: Expr) -> Result<(), LoxError> {
-        let val = self.eval_expr(&e)?;
+    fn return_stm(&mut self, e: Option<Expr>) -> Result<(), LoxError> {
+        let val = match e {
+            Some(e) => self.eval_expr(&e)?,
+            None => Object::Nil,
+        };
         Err(LoxError::Return(val))
     }
 
+    fn import_stm(&mut self, path: &Token, imports: Imports) -> Result<(), LoxError> {
+        let module = self.load_module(path)?;
+        match imports {
+            Imports::Module(name) => self.env.define(name.lexeme, Object::Module(module)),
+            Imports::Names(names) => {
+                for name in names {
+                    let value = module.get(&name)?;
+                    self.env.define(name.lexeme, value);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Runs the module at `path` in a global scope of its own, once.
+    fn load_module(&mut self, path: &Token) -> Result<LoxModule, LoxError> {
+        let Some(Object::String(relative)) = &path.literal else {
+            unreachable!("module paths are string literals");
+        };
+        let base = self.loading.last().and_then(|file| file.parent());
+        let file = base.map_or_else(|| PathBuf::from(relative), |dir| dir.join(relative));
+        let not_found = |err: io::Error| -> LoxError {
+            Diagnostic::error(
+                ErrorCode::ModuleNotFound,
+                format!("Can't load module '{}'.", relative),
+            )
+            .with_span_label(path.span, err.to_string())
+            .with_note(format!("looked for {}", file.display()))
+            .into()
+        };
+        let canonical = fs::canonicalize(&file).map_err(not_found)?;
+
+        if let Some(module) = self.modules.get(&canonical) {
+            return Ok(module.clone());
+        }
+        if let Some(i) = self.loading.iter().position(|file| *file == canonical) {
+            let cycle: Vec<String> = self.loading[i..]
+                .iter()
+                .chain(std::iter::once(&canonical))
+                .map(|file| file.display().to_string())
+                .collect();
+            return Err(Diagnostic::error(
+                ErrorCode::CircularImport,
+                format!("Circular import of '{}'.", relative),
+            )
+            .with_span(path.span)
+            .with_note(format!("the imports form a cycle: {}", cycle.join(" -> ")))
+            .into());
+        }
+
+        let source = fs::read_to_string(&canonical).map_err(not_found)?;
+        let statements = Self::compile(&source).map_err(|errors| {
+            errors.into_iter().fold(
+                Diagnostic::error(
+                    ErrorCode::InvalidModule,
+                    format!("Module '{}' failed to compile.", relative),
+                )
+                .with_span(path.span),
+                |diagnostic, err| {
+                    let line = err.span().map_or(0, |span| span.line);
+                    diagnostic.with_note(format!("{}:{}: {}", relative, line, err.message))
+                },
+            )
+        })?;
+
+        // Shown relative to the working directory when it's inside it
+        let name = env::current_dir()
+            .ok()
+            .and_then(|dir| canonical.strip_prefix(dir).ok())
+            .unwrap_or(&canonical)
+            .display()
+            .to_string();
+        let file = Rc::new(SourceFile { name, source });
+
+        // Only built-ins are shared, not the importer's globals
+        let globals = Environment::new_global(&self.builtins);
+        let previous = mem::replace(&mut self.env, globals.clone());
+        self.loading.push(canonical.clone());
+        let result = self.in_file(Some(file), |i| {
+            statements
+                .into_iter()
+                .try_for_each(|stmt| i.eval_stmt(stmt))
+        });
+        self.loading.pop();
+        self.env = previous;
+        result.map_err(|err| self.in_module(err, relative, path.span))?;
+
+        let name = canonical.file_stem().unwrap_or_default().to_string_lossy();
+        let module = LoxModule::new(name, globals);
+        self.modules.insert(canonical, module.clone());
+        Ok(module)
+    }
+
+    // Moves an error raised while running a module onto the import, as its
+    // span points into the module's source.
+    fn in_module(&self, err: LoxError, module: &str, import: Span) -> LoxError {
+        let relocate = |diagnostic: Box<Diagnostic>| {
+            let location = diagnostic
+                .span()
+                .map_or(String::new(), |span| format!(":{}", span));
+            let mut notes = vec![format!("raised at {}{}", module, location)];
+            notes.extend(diagnostic.notes);
+            Box::new(
+                Diagnostic {
+                    primary: None,
+                    secondary: Vec::new(),
+                    notes,
+                    suggestions: Vec::new(),
+                    ..*diagnostic
+                }
+                .with_span_label(import, "in this import"),
+            )
+        };
+        let trace = self.frames.iter().rev().cloned().collect();
+        match err {
+            LoxError::Diagnostic(diagnostic) => LoxError::Diagnostic(relocate(diagnostic)),
+            LoxError::Runtime(error) => LoxError::Diagnostic(relocate(error.diagnostic)),
+            LoxError::Throw(mut thrown) => {
+                thrown.error = RuntimeError {
+                    diagnostic: relocate(thrown.error.diagnostic),
+                    trace,
+                    file: self.file.clone(),
+                };
+                LoxError::Throw(thrown)
+            }
+            e => e,
+        }
+    }
+
+    fn throw_stm(&mut self, e: Expr, span: Span) -> Result<(), LoxError> {
+        let value = self.eval_expr(&e)?;
+        let error = RuntimeError {
+            diagnostic: Box::new(
+                Diagnostic::error(ErrorCode::Uncaught, uncaught_message(&value)).with_span(span),
+            ),
+            trace: self.frames.iter().rev().cloned().collect(),
+            file: self.file.clone(),
+        };
+
+        // A rethrown error keeps the stack of where it was first thrown
+        if let Object::Instance(instance) = &value {
+            if instance.class().is_subclass_of(&self.error_class)
+                && matches!(instance.get_property("stack"), Ok(Object::Nil))
+            {
+                instance.set_field("stack", Object::String(error.stack()));
+            }
+        }
+        Err(LoxError::Throw(Box::new(Thrown { value, error })))
+    }
+
+    fn try_stm(
+        &mut self,
+        body: Statement,
+        catch: Option<(Token, Box<Statement>)>,
+        finally: Option<Box<Statement>>,
+    ) -> Result<(), LoxError> {
+        let mut result = self.eval_stmt(body);
+
+        if let (Err(err), Some((name, handler))) = (&result, catch) {
+            // `return`, `break` and the like aren't errors and pass through
+            if let Some(value) = self.caught(err) {
+                let mut env = Environment::new_with_enclosing(&self.env);
+                env.define(name.lexeme, value);
+                result = self.exec_block(&[*handler], env);
+            }
+        }
+
+        if let Some(finally) = finally {
+            self.eval_stmt(*finally)?;
+        }
+        result
+    }
+
+    // The value a `catch` clause binds for `err`. Runtime errors become
+    // instances of `Error`.
+    fn caught(&self, err: &LoxError) -> Option<Object> {
+        let error = match err {
+            LoxError::Throw(thrown) => return Some(thrown.value.clone()),
+            LoxError::Runtime(error) => (**error).clone(),
+            LoxError::Diagnostic(diagnostic) => RuntimeError {
+                diagnostic: diagnostic.clone(),
+                trace: self.frames.iter().rev().cloned().collect(),
+                file: self.file.clone(),
+            },
+            _ => return None,
+        };
+        let instance = LoxInstance::new(self.error_class.clone());
+        instance.set_field("message", Object::String(error.diagnostic.message.clone()));
+        instance.set_field("stack", Object::String(error.stack()));
+        Some(Object::Instance(instance))
+    }
+
     fn block(&mut self, stms: VecDeque<Statement>) -> Result<(), LoxError> {
         let new_env = Environment::new_with_enclosing(&self.env);
         let vec_stms: Vec<Statement> = stms.into_iter().collect();
@@ -197,73 +633,154 @@ impl Interpreter {
 
     fn eval_print(&mut self, e: Expr) -> Result<(), LoxError> {
         let val = self.eval_expr(&e)?;
-        println!("{}", val);
+        writeln!(self.stdout, "{}", val)?;
         Ok(())
     }
 
     fn eval_expr(&mut self, expr: &Expr) -> Result<Object, LoxError> {
-         match expr {
-            Expr::Literal(o) => Ok(o.clone()),
-            Expr::Grouping(e) => self.eval_expr(e.as_ref()),
-            Expr::Unary(t, e) => self.unary_expr(e.as_ref(), t),
-            Expr::Binary(e1, t, e2) => self.binary_expr(e1.as_ref(), e2.as_ref(), t),
-            Expr::Variable(var) => {
-                let res = self.env.get_at(var);
-                
-                if let Ok(o) = res {
-                    Ok(o)
+        self.eval_expr_kind(expr).map_err(|e| e.or_span(expr.span))
+    }
+
+    fn eval_expr_kind(&mut self, expr: &Expr) -> Result<Object, LoxError> {
+        match &expr.kind {
+            ExprKind::Literal(o) => Ok(o.clone()),
+            ExprKind::Grouping(e) => self.eval_expr(e.as_ref()),
+            ExprKind::Unary(t, e) => self.unary_expr(e.as_ref(), t),
+            ExprKind::Binary(e1, t, e2) => self.binary_expr(e1.as_ref(), e2.as_ref(), t),
+            ExprKind::Variable(var) => self.env.get_at(var),
+            ExprKind::Assignment(var, e) => self.assign_expr(e.as_ref(), var),
+            ExprKind::Logical(e1, op, e2) => self.logical_expr(e1.as_ref(), e2.as_ref(), op),
+            ExprKind::Conditional(condition, then_branch, else_branch) => {
+                if self.eval_expr(condition)?.is_truthy() {
+                    self.eval_expr(then_branch)
                 } else {
-                    Err(LoxError::NotExpression)
+                    self.eval_expr(else_branch)
                 }
             }
-            Expr::Assignment(var, e) => self.assign_expr(e.as_ref(), var),
-            Expr::Logical(e1, op, e2) => self.logical_expr(e1.as_ref(), e2.as_ref(), op),
-            Expr::Call(callee, args) => self.call_expr(callee.as_ref(), args),
-            Expr::Get(e, name) => self.get_expr(e.as_ref(), name),
-            Expr::Set(e1, name, e2) => self.set_expr(e1.as_ref(), e2.as_ref(), name),
-            Expr::This(var) => Ok(self.env.get_at(var)?),
-            Expr::Super(var, key) => {
-                let mut var = var.clone();
-                var.hops -= 2;
-                if let Object::Class(superclass) = self.env.get_at(&var)? {
-                    let method = superclass.find_method(&key.lexeme).unwrap();
-                    let obj = self.env.get_at(&Var::new_wo_token("this", var.hops - 1))?;
-                    if let Object::Instance(instance) = obj {
-                        return Ok(Object::Callable(Rc::new(Box::new(method.bind(instance)))));
-                    }
+            ExprKind::Call(callee, args) => self.call_expr(callee.as_ref(), args),
+            ExprKind::Get(e, name) => self.get_expr(e.as_ref(), name),
+            ExprKind::Set(e1, name, e2) => self.set_expr(e1.as_ref(), e2.as_ref(), name),
+            ExprKind::Lambda(name, params, body) => {
+                let f = LoxFunction::new(
+                    name.clone(),
+                    params.clone(),
+                    (**body).clone(),
+                    self.env.clone(),
+                    false,
+                )
+                .in_file(self.file.clone());
+                Ok(Object::Callable(Rc::new(Box::new(f))))
+            }
+            ExprKind::List(elements) => {
+                let items = elements
+                    .iter()
+                    .map(|e| self.eval_expr(e))
+                    .collect::<Result<_, _>>()?;
+                Ok(Object::List(LoxList::new(items)))
+            }
+            ExprKind::Map(entries) => {
+                let map = LoxMap::new();
+                for (key, value) in entries {
+                    let k = self.eval_expr(key)?;
+                    let v = self.eval_expr(value)?;
+                    map.set(k, v).map_err(|err| err.or_span(key.span))?;
+                }
+                Ok(Object::Map(map))
+            }
+            ExprKind::Index(e, _, index) => {
+                let object = self.eval_expr(e)?;
+                let key = self.eval_expr(index)?;
+                get_index(e, &object, &key).map_err(|err| err.or_span(index.span))
+            }
+            ExprKind::Chain(e) => Ok(self.eval_link(e)?.unwrap_or(Object::Nil)),
+            ExprKind::Optional(e) => self.eval_expr(e),
+            ExprKind::SetIndex(e, _, index, value) => {
+                let object = self.eval_expr(e)?;
+                let key = self.eval_expr(index)?;
+                let value = self.eval_expr(value)?;
+                match &object {
+                    Object::List(list) => list.set(&key, value.clone()),
+                    Object::Map(map) => map.set(key, value.clone()),
+                    _ => Err(not_indexable(e, &object)),
+                }
+                .map_err(|err| err.or_span(index.span))?;
+                Ok(value)
+            }
+            ExprKind::Update(target, op, value, postfix) => {
+                self.update_expr(target.as_ref(), op, value.as_ref(), *postfix)
+            }
+            ExprKind::This(var) => Ok(self.env.get_at(var)?),
+            ExprKind::Super(var, key) => {
+                let superclass = self.env.get_at(var)?;
+                // `this` lives in the scope right inside the one binding `super`
+                let this = Var::new_wo_token("this", var.hops.unwrap_or(1).saturating_sub(1));
+                if let (Object::Class(superclass), Object::Instance(instance)) =
+                    (superclass, self.env.get_at(&this)?)
+                {
+                    let method = superclass.find_method(&key.lexeme).ok_or_else(|| {
+                        Diagnostic::error(
+                            ErrorCode::UndefinedProperty,
+                            format!("Undefined property '{}'.", key.lexeme),
+                        )
+                        .with_span(key.span)
+                    })?;
+                    return Ok(Object::Callable(Rc::new(method.bind(instance))));
                 }
-                Err(LoxError::Error("superclass".to_string()))
+                Err(LoxError::new(
+                    ErrorCode::SuperclassNotClass,
+                    "Superclass must be a class.",
+                ))
             }
         }
     }
 
+    // Evaluates part of an optional chain, or returns None once a `?.`
+    // receiver is nil so that the rest of the chain is skipped. Each
+    // receiver is evaluated once, as in an ordinary chain.
+    fn eval_link(&mut self, expr: &Expr) -> Result<Option<Object>, LoxError> {
+        let value = match &expr.kind {
+            ExprKind::Optional(e) => {
+                let receiver = self.eval_link(e)?;
+                return Ok(receiver.filter(|object| !matches!(object, Object::Nil)));
+            }
+            ExprKind::Get(e, name) => {
+                let Some(object) = self.eval_link(e)? else {
+                    return Ok(None);
+                };
+                get_property(e, &object, name)
+            }
+            ExprKind::Call(callee, args) => {
+                let Some(function) = self.eval_link(callee)? else {
+                    return Ok(None);
+                };
+                self.finish_call(&function, callee.span, args)
+            }
+            ExprKind::Index(e, _, index) => {
+                let Some(object) = self.eval_link(e)? else {
+                    return Ok(None);
+                };
+                let key = self.eval_expr(index)?;
+                get_index(e, &object, &key).map_err(|err| err.or_span(index.span))
+            }
+            _ => return self.eval_expr(expr).map(Some),
+        };
+        value.map(Some).map_err(|err| err.or_span(expr.span))
+    }
+
     fn get_expr(&mut self, e: &Expr, name: &Token) -> Result<Object, LoxError> {
         let object = self.eval_expr(e)?;
-        if let Object::Instance(instance) = &object {
-            return instance.get(name);
-        }
-        Err(LoxError::Error(format!("{} is not a instance", object)))
+        get_property(e, &object, name)
     }
 
     fn set_expr(&mut self, e1: &Expr, e2: &Expr, name: &Token) -> Result<Object, LoxError> {
-        let value = self.eval_expr(e1)?;
-        let (mut instance, var) = {
-            if let Expr::Variable(var) = e2 {
-                (self.env.get_at(var)?, var)
-            } else if let Expr::This(var) = e2 {
-                (self.env.get_at(var)?, var)
-            } else {
-                return Err(LoxError::Error(format!("Eval set error value: {} var: {:?}", value, e2)));
-            }
+        let object = self.eval_expr(e2)?;
+        let Object::Instance(mut instance) = object else {
+            return Err(no_fields(e2, &object));
         };
 
-
-        if let Object::Instance(lox_instance) = &mut instance {
-            lox_instance.set(name, value.clone())?;
-            self.env.assign(var.name().to_string(), Object::Instance(lox_instance.clone()))?;
-            return Ok(value);
-        }
-        Err(LoxError::Error(format!("{} is not a instance", value)))
+        let value = self.eval_expr(e1)?;
+        instance.set(name, value.clone())?;
+        Ok(value)
     }
 
     fn logical_expr(&mut self, e1: &Expr, e2: &Expr, op: &Token) -> Result<Object, LoxError> {
@@ -283,6 +800,10 @@ impl Interpreter {
                     Ok(self.eval_expr(e2)?)
                 }
             }
+            TokenType::QUESTION_QUESTION => match left {
+                Object::Nil => Ok(self.eval_expr(e2)?),
+                _ => Ok(left),
+            },
             _ => unreachable!(),
         }
     }
@@ -290,8 +811,8 @@ impl Interpreter {
     fn assign_expr(&mut self, e: &Expr, var: &Var) -> Result<Object, LoxError> {
         let val = self.eval_expr(e)?;
 
-        self.env.assign_at(var, val)?;
-        Ok(Object::Nil)
+        self.env.assign_at(var, val.clone())?;
+        Ok(val)
     }
 
     fn unary_expr(&mut self, e: &Expr, operator: &Token) -> Result<Object, LoxError> {
@@ -299,7 +820,7 @@ impl Interpreter {
 
         if operator.token_type == TokenType::MINUS {
             if let Object::Number(n) = right {
-                return Ok(Object::Number(n * -1.0));
+                return Ok(Object::Number(-n));
             }
         }
 
@@ -307,7 +828,12 @@ impl Interpreter {
             return Ok(Object::Boolean(!right.is_truthy()));
         }
 
-        unreachable!();
+        Err(
+            Diagnostic::error(ErrorCode::OperandMustBeNumber, "Operand must be a number.")
+                .with_span_label(e.span, format!("this is {}", right.repr()))
+                .with_label(operator.span, "expected a number for this operator")
+                .into(),
+        )
     }
 
     fn binary_expr(
@@ -316,82 +842,534 @@ impl Interpreter {
         right: &Expr,
         operator: &Token,
     ) -> Result<Object, LoxError> {
+        let (left_span, right_span) = (left.span, right.span);
         let left = self.eval_expr(left)?;
         let right = self.eval_expr(right)?;
+        binary(left, left_span, operator, right, right_span)
+    }
 
-        let is_num = |x: &Object| x.clone().get_v_num().is_ok();
-
-        let to_num = Object::Number;
-        let to_str = Object::String;
-        let to_bool = Object::Boolean;
-
-        let obj = {
-            match operator.token_type {
-                TokenType::MINUS => to_num(left.get_v_num()? - right.get_v_num()?),
-                TokenType::SLASH => to_num(left.get_v_num()? / right.get_v_num()?),
-                TokenType::STAR => to_num(left.get_v_num()? * right.get_v_num()?),
-                TokenType::PLUS => {
-                    if is_num(&left) && is_num(&right) {
-                        to_num(left.get_v_num()? + right.get_v_num()?)
-                    } else {
-                        to_str(left.get_v_string()? + &right.get_v_string()?)
-                    }
+    fn update_expr(
+        &mut self,
+        target: &Expr,
+        operator: &Token,
+        value: &Expr,
+        postfix: bool,
+    ) -> Result<Object, LoxError> {
+        let (old, new) = match &target.kind {
+            ExprKind::Variable(var) => {
+                let old = self.env.get_at(var)?;
+                let new = self.updated(&old, target, operator, value)?;
+                self.env.assign_at(var, new.clone())?;
+                (old, new)
+            }
+            ExprKind::Get(e, name) => {
+                let object = self.eval_expr(e)?;
+                let Object::Instance(mut instance) = object else {
+                    return Err(no_fields(e, &object));
+                };
+                let old = instance.get(name)?;
+                let new = self.updated(&old, target, operator, value)?;
+                instance.set(name, new.clone())?;
+                (old, new)
+            }
+            ExprKind::Index(e, _, index) => {
+                let object = self.eval_expr(e)?;
+                let key = self.eval_expr(index)?;
+                let old = get_index(e, &object, &key).map_err(|err| err.or_span(index.span))?;
+                let new = self.updated(&old, target, operator, value)?;
+                match &object {
+                    Object::List(list) => list.set(&key, new.clone()),
+                    Object::Map(map) => map.set(key, new.clone()),
+                    _ => unreachable!(),
                 }
-                TokenType::GREATER => to_bool(left.get_v_num()? > right.get_v_num()?),
-                TokenType::GREATER_EQUAL => to_bool(left.get_v_num()? >= right.get_v_num()?),
-                TokenType::LESS => to_bool(left.get_v_num()? < right.get_v_num()?),
-                TokenType::LESS_EQUAL => to_bool(left.get_v_num()? <= right.get_v_num()?),
-                TokenType::BANG_EQUAL => to_bool(!Object::is_equal(left, right)),
-                TokenType::EQUAL_EQUAL => to_bool(Object::is_equal(left, right)),
-                _ => unreachable!(),
+                .map_err(|err| err.or_span(index.span))?;
+                (old, new)
             }
+            _ => unreachable!(),
         };
-        Ok(obj)
+        Ok(if postfix { old } else { new })
     }
 
-    fn call_expr(&mut self, callee: &Expr, args: &[Expr]) -> Result<Object, LoxError> {
-        let callee = self.eval_expr(callee)?;
+    // The new value of an update whose target currently holds `old`
+    fn updated(
+        &mut self,
+        old: &Object,
+        target: &Expr,
+        operator: &Token,
+        value: &Expr,
+    ) -> Result<Object, LoxError> {
+        let right = self.eval_expr(value)?;
+        binary(old.clone(), target.span, operator, right, value.span)
+    }
 
+    fn call_expr(&mut self, callee: &Expr, args: &[Expr]) -> Result<Object, LoxError> {
+        let function = self.eval_expr(callee)?;
+        self.finish_call(&function, callee.span, args)
+    }
 
+    fn finish_call(
+        &mut self,
+        callee: &Object,
+        callee_span: Span,
+        args: &[Expr],
+    ) -> Result<Object, LoxError> {
         let mut arguments = Vec::with_capacity(args.len());
         for e in args {
             arguments.push(self.eval_expr(e)?);
         }
 
-        match callee {
-            Object::Callable(function) => {
-                if function.arity() != arguments.len() {
-                    return Err(LoxError::Error(format!(
-                        "Expected {} arguments but got {}.",
+        self.call_value(callee, &arguments, callee_span)
+    }
+
+    fn call_value(
+        &mut self,
+        callee: &Object,
+        arguments: &[Object],
+        callee_span: Span,
+    ) -> Result<Object, LoxError> {
+        let function: &dyn Callable = match &callee {
+            Object::Callable(function) => function.as_ref().as_ref(),
+            Object::Class(class) => class,
+            _ => {
+                return Err(Diagnostic::error(
+                    ErrorCode::NotCallable,
+                    "Can only call functions and classes.",
+                )
+                .with_span_label(callee_span, format!("this is {}", callee.repr()))
+                .into())
+            }
+        };
+
+        let (arity_ok, at_least) = if function.is_variadic() {
+            (arguments.len() >= function.arity(), "at least ")
+        } else {
+            (arguments.len() == function.arity(), "")
+        };
+        if !arity_ok {
+            return Err(LoxError::from(
+                Diagnostic::error(
+                    ErrorCode::ArityMismatch,
+                    format!(
+                        "Expected {}{} arguments but got {}.",
+                        at_least,
                         function.arity(),
                         arguments.len()
-                    )));
-                }
-                function.call(self, &arguments)
-            }
-            Object::Class(class) => class.call(self, &arguments),
-            _ => unreachable!(),
+                    ),
+                )
+                .with_label(
+                    callee_span,
+                    format!(
+                        "'{}' takes {}{} arguments",
+                        function.name(),
+                        at_least,
+                        function.arity()
+                    ),
+                ),
+            ));
+        }
+
+        if self.frames.is_empty() {
+            self.stack_base = stack_address();
+        }
+        let stack = self.stack_base.abs_diff(stack_address());
+        if self.frames.len() == MAX_FRAMES || stack > MAX_STACK {
+            return Err(
+                Diagnostic::error(ErrorCode::StackOverflow, "Stack overflow.")
+                    .with_span_label(callee_span, "one call too many")
+                    .with_note(format!(
+                        "calls can be nested at most {} deep, fewer when they use a lot of stack",
+                        MAX_FRAMES
+                    ))
+                    .into(),
+            );
+        }
+
+        self.frames.push(Frame {
+            function: function.name(),
+            class: function.class_name(),
+            call_site: callee_span,
+            file: self.file.clone(),
+        });
+        let result = function
+            .call(self, arguments)
+            .map_err(|e| self.capture_trace(e));
+        self.frames.pop();
+        result
+    }
+
+    /// Runs `body` as code declared in `file`, a module or `None` for the
+    /// main script, so errors raised in it render against that file.
+    pub(crate) fn in_file<T>(
+        &mut self,
+        file: Option<Rc<SourceFile>>,
+        body: impl FnOnce(&mut Self) -> Result<T, LoxError>,
+    ) -> Result<T, LoxError> {
+        let caller = mem::replace(&mut self.file, file);
+        let result = body(self).map_err(|e| self.capture_trace(e));
+        self.file = caller;
+        result
+    }
+
+    // Snapshots the active calls, and the file running, into an error
+    // raised inside the innermost one. Errors that already carry a trace
+    // are passed through untouched.
+    fn capture_trace(&self, err: LoxError) -> LoxError {
+        match err {
+            LoxError::Diagnostic(diagnostic) => LoxError::Runtime(Box::new(RuntimeError {
+                diagnostic,
+                trace: self.frames.iter().rev().cloned().collect(),
+                file: self.file.clone(),
+            })),
+            e => e,
         }
     }
 }
 
-impl Interpreter {
-    fn is_at_end(&self) -> bool {
-        self.current >= self.statements.len()
+// The address of a local, to measure how much stack is in use between
+// two calls.
+fn stack_address() -> usize {
+    let marker = 0u8;
+    &marker as *const u8 as usize
+}
+
+// The message for a value that no `catch` handled, e.g. "Uncaught Error:
+// file not found" for an `Error`.
+fn uncaught_message(value: &Object) -> String {
+    if let Object::Instance(instance) = value {
+        if let Ok(Object::String(message)) = instance.get_property("message") {
+            return format!("Uncaught {}: {}", instance.class().name(), message);
+        }
     }
+    format!("Uncaught {}.", value.repr())
+}
 
-    fn advance(&mut self) -> Result<Statement, LoxError> {
-        if let Some(statement) = self.statements.get(self.current) {
-            self.current += 1;
-            Ok(statement.clone())
-        } else {
-            Err(LoxError::Error(
-                "Interpreter error: advance not possible".to_string(),
+fn binary(
+    left: Object,
+    left_span: Span,
+    operator: &Token,
+    right: Object,
+    right_span: Span,
+) -> Result<Object, LoxError> {
+    let numbers = || match (&left, &right) {
+        (Object::Number(a), Object::Number(b)) => Ok((*a, *b)),
+        _ => Err(LoxError::from(
+            Diagnostic::error(
+                ErrorCode::OperandsMustBeNumbers,
+                "Operands must be numbers.",
+            )
+            .with_span(operator.span)
+            .with_label(left_span, format!("this is {}", left.repr()))
+            .with_label(right_span, format!("this is {}", right.repr())),
+        )),
+    };
+
+    // `/` follows IEEE 754 and gives an infinity or NaN for a zero
+    // divisor. `%` and `~/` have no useful result there and fail.
+    let divisor = |b: f64| {
+        if b == 0.0 {
+            Err(LoxError::from(
+                Diagnostic::error(ErrorCode::DivisionByZero, "Division by zero.")
+                    .with_span(operator.span)
+                    .with_label(right_span, "this is 0"),
             ))
+        } else {
+            Ok(b)
         }
+    };
+
+    let to_num = Object::Number;
+    let to_str = Object::String;
+    let to_bool = Object::Boolean;
+
+    let obj = {
+        match operator.token_type {
+            TokenType::MINUS => numbers().map(|(a, b)| to_num(a - b))?,
+            TokenType::SLASH => numbers().map(|(a, b)| to_num(a / b))?,
+            TokenType::STAR => numbers().map(|(a, b)| to_num(a * b))?,
+            // The remainder takes the sign of the dividend: -7 % 3 is -1
+            TokenType::PERCENT => {
+                let (a, b) = numbers()?;
+                to_num(a % divisor(b)?)
+            }
+            // Truncates toward zero: -7 ~/ 2 is -3
+            TokenType::TILDE_SLASH => {
+                let (a, b) = numbers()?;
+                to_num((a / divisor(b)?).trunc())
+            }
+            TokenType::STAR_STAR => numbers().map(|(a, b)| to_num(a.powf(b)))?,
+            TokenType::PLUS => match (&left, &right) {
+                (Object::Number(a), Object::Number(b)) => to_num(a + b),
+                (Object::String(a), Object::String(b)) => to_str(a.clone() + b),
+                _ => {
+                    return Err(Diagnostic::error(
+                        ErrorCode::OperandsMustBeNumbersOrStrings,
+                        "Operands must be two numbers or two strings.",
+                    )
+                    .with_span(operator.span)
+                    .with_label(left_span, format!("this is {}", left.repr()))
+                    .with_label(right_span, format!("this is {}", right.repr()))
+                    .into())
+                }
+            },
+            TokenType::GREATER => numbers().map(|(a, b)| to_bool(a > b))?,
+            TokenType::GREATER_EQUAL => numbers().map(|(a, b)| to_bool(a >= b))?,
+            TokenType::LESS => numbers().map(|(a, b)| to_bool(a < b))?,
+            TokenType::LESS_EQUAL => numbers().map(|(a, b)| to_bool(a <= b))?,
+            TokenType::BANG_EQUAL => to_bool(!Object::is_equal(left, right)),
+            TokenType::EQUAL_EQUAL => to_bool(Object::is_equal(left, right)),
+            _ => unreachable!(),
+        }
+    };
+    Ok(obj)
+}
+
+fn get_property(e: &Expr, object: &Object, name: &Token) -> Result<Object, LoxError> {
+    match object {
+        Object::Instance(instance) => instance.get(name),
+        Object::List(list) => list.method(name),
+        Object::Map(map) => map.method(name),
+        Object::Module(module) => module.get(name),
+        Object::String(s) => string::method(s, name),
+        _ => Err(
+            Diagnostic::error(ErrorCode::NotAnInstance, "Only instances have properties.")
+                .with_span_label(e.span, format!("this is {}", object.repr()))
+                .into(),
+        ),
+    }
+}
+
+fn get_index(e: &Expr, object: &Object, key: &Object) -> Result<Object, LoxError> {
+    match object {
+        Object::List(list) => list.get(key),
+        Object::Map(map) => map.get(key),
+        _ => Err(not_indexable(e, object)),
+    }
+}
+
+fn no_fields(e: &Expr, object: &Object) -> LoxError {
+    Diagnostic::error(ErrorCode::NotAnInstance, "Only instances have fields.")
+        .with_span_label(e.span, format!("this is {}", object.repr()))
+        .into()
+}
+
+fn not_indexable(e: &Expr, object: &Object) -> LoxError {
+    Diagnostic::error(
+        ErrorCode::NotIndexable,
+        "Only lists and maps can be indexed.",
+    )
+    .with_span_label(e.span, format!("this is {}", object.repr()))
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::OutputBuffer;
+
+    fn run(source: &str) -> Result<Object, RuntimeError> {
+        let stms = Interpreter::compile(source).unwrap();
+        Interpreter::builder().build().interpret(stms)
+    }
+
+    #[test]
+    fn eval_keeps_globals() {
+        let mut lox = Interpreter::builder()
+            .global("base", Object::Number(10.0))
+            .build();
+        lox.eval("fun add(n) { return base + n; }").unwrap();
+        lox.eval("var total = add(1);").unwrap();
+        assert_eq!(lox.eval("add(total);").unwrap(), Object::Number(21.0));
+
+        lox.set_global("base", Object::Number(0.0));
+        assert_eq!(lox.eval("add(2);").unwrap(), Object::Number(2.0));
+        assert_eq!(lox.get_global("total"), Some(Object::Number(11.0)));
+        assert_eq!(lox.get_global("missing"), None);
+
+        assert!(matches!(lox.eval("var;"), Err(EvalError::Compile(_))));
+        assert!(matches!(lox.eval("add(nil);"), Err(EvalError::Runtime(_))));
+        assert_eq!(lox.eval("total;").unwrap(), Object::Number(11.0));
+    }
+
+    #[test]
+    fn runtime_error_trace() {
+        let source = "class Book { name() { return -\"x\"; } }\n\
+                      fun show(b) { b.name(); }\n\
+                      show(Book());";
+        let err = run(source).unwrap_err();
+        assert_eq!(err.diagnostic.code, ErrorCode::OperandMustBeNumber);
+        assert_eq!(err.span().map(|s| s.line), Some(1));
+
+        let trace: Vec<(String, usize)> = err
+            .trace
+            .iter()
+            .map(|f| (f.to_string(), f.call_site.line))
+            .collect();
+        assert_eq!(
+            trace,
+            vec![("Book.name".to_string(), 2), ("show".to_string(), 3)]
+        );
+        assert_eq!(
+            err.backtrace("book.lox"),
+            "  at Book.name (book.lox:1:31)\n  at show (book.lox:2:15)\n  at <script> (book.lox:3:1)\n"
+        );
+    }
+
+    #[test]
+    fn deep_recursion_is_an_error() {
+        // Spawned threads get 2 MiB of stack unless told otherwise
+        let test = std::thread::spawn(|| {
+            let bodies = [
+                "return f(n + 1);".to_string(),
+                "while (true) { for (;;) { if (true) { f(n + 1); } } }".to_string(),
+                format!("return {}f(n + 1){} + 1;", "(".repeat(10), ")".repeat(10)),
+            ];
+            for body in bodies {
+                let err = run(&format!("fun f(n) {{ {} }} f(0);", body)).unwrap_err();
+                assert_eq!(err.diagnostic.code, ErrorCode::StackOverflow);
+            }
+
+            let mut lox = Interpreter::builder().build();
+            lox.eval("fun f(n) { return f(n + 1); }").unwrap();
+            let f = lox.get_global("f").unwrap();
+            let err = lox.call(&f, (0,)).unwrap_err();
+            assert_eq!(err.diagnostic.code, ErrorCode::StackOverflow);
+        });
+        test.join().unwrap();
     }
 
-    fn peek(&self) -> Statement {
-        self.statements.get(self.current).unwrap().clone()
+    #[test]
+    fn module_errors_render_their_file() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/lox/module");
+        let source = "import \"lib/faulty.lox\" as faulty;\nfaulty.outer(1);";
+        let mut lox = Interpreter::builder().build();
+        let Err(EvalError::Runtime(err)) = lox.eval_script(dir.join("main.lox"), source) else {
+            panic!("expected a runtime error");
+        };
+
+        let file = err.file.as_ref().map(|file| file.name.replace('\\', "/"));
+        assert!(file.is_some_and(|name| name.ends_with("lib/faulty.lox")));
+        assert_eq!(err.span().map(|s| s.line), Some(3));
+        assert_eq!(err.script_span().map(|s| s.line), Some(2));
+
+        let rendered = err.render("main.lox", source);
+        assert!(rendered.contains("faulty.lox:3:12\n"));
+        assert!(rendered.contains("3 |   return n + nil;"));
+        assert!(rendered.ends_with("faulty.lox:7:10)\n  at <script> (main.lox:2:1)\n"));
+    }
+
+    #[test]
+    fn native_functions() {
+        let mut lox = Interpreter::builder().build();
+        lox.define_native("repeat", 2, |args| {
+            let (s, n): (String, usize) = args.extract()?;
+            Ok(s.repeat(n))
+        });
+        lox.define_native_variadic("sum", 0, |args| {
+            let mut total = 0.0;
+            for i in 0..args.len() {
+                total += args.num(i)?;
+            }
+            Ok(Object::Number(total))
+        });
+
+        assert_eq!(
+            lox.eval("repeat(\"ab\", 2);").unwrap(),
+            Object::String("abab".to_string())
+        );
+        assert_eq!(lox.eval("sum();").unwrap(), Object::Number(0.0));
+        assert_eq!(lox.eval("sum(1, 2, 3);").unwrap(), Object::Number(6.0));
+
+        let Err(EvalError::Runtime(err)) = lox.eval("sum(1, \"2\");") else {
+            panic!("expected a runtime error");
+        };
+        assert_eq!(err.diagnostic.code, ErrorCode::TypeMismatch);
+        assert_eq!(err.diagnostic.notes, vec!["in argument 2 of 'sum'"]);
+
+        let Err(EvalError::Runtime(err)) = lox.eval("repeat(\"ab\");") else {
+            panic!("expected a runtime error");
+        };
+        assert_eq!(err.diagnostic.code, ErrorCode::ArityMismatch);
+    }
+
+    #[test]
+    fn call_from_host() {
+        let mut lox = Interpreter::builder().build();
+        lox.eval(
+            "fun twice(n) { return n * 2; }\n\
+             class Counter { init() { this.n = 0; } add(k) { this.n = this.n + k; return this.n; } }\n\
+             var counter = Counter();",
+        )
+        .unwrap();
+
+        let twice = lox.get_global("twice").unwrap();
+        assert_eq!(
+            lox.call(&twice, &[Object::Number(4.0)]).unwrap(),
+            Object::Number(8.0)
+        );
+        assert_eq!(lox.call(&twice, (5,)).unwrap(), Object::Number(10.0));
+        let err = lox.call(&twice, &[]).unwrap_err();
+        assert_eq!(err.diagnostic.code, ErrorCode::ArityMismatch);
+
+        let Some(Object::Instance(counter)) = lox.get_global("counter") else {
+            panic!("expected an instance");
+        };
+        lox.call_method(&counter, "add", &[Object::Number(2.0)])
+            .unwrap();
+        assert_eq!(
+            lox.call_method(&counter, "add", &[Object::Number(3.0)])
+                .unwrap(),
+            Object::Number(5.0)
+        );
+
+        let err = lox
+            .call_method(&counter, "add", &[Object::Nil])
+            .unwrap_err();
+        assert_eq!(
+            err.diagnostic.code,
+            ErrorCode::OperandsMustBeNumbersOrStrings
+        );
+        assert_eq!(
+            err.backtrace("plugin.lox"),
+            "  at Counter.add (plugin.lox:2:65)\n"
+        );
+
+        let err = lox.call_method(&counter, "missing", &[]).unwrap_err();
+        assert_eq!(err.diagnostic.code, ErrorCode::UndefinedProperty);
+    }
+
+    #[test]
+    fn callables_are_equal_only_to_themselves() {
+        let mut lox = Interpreter::builder().build();
+        lox.eval("fun make() { return (x) => x; }\nvar a = make();\nvar b = make();\nvar c = a;")
+            .unwrap();
+        let (a, b, c) = (
+            lox.get_global("a").unwrap(),
+            lox.get_global("b").unwrap(),
+            lox.get_global("c").unwrap(),
+        );
+        assert_eq!(a, c);
+        assert_ne!(a, b);
+        assert_eq!(lox.eval("a == b;").unwrap(), Object::Boolean(false));
+    }
+
+    #[test]
+    fn output_sinks() {
+        let out = OutputBuffer::new();
+        let err = OutputBuffer::new();
+        let mut lox = Interpreter::builder()
+            .stdout(out.clone())
+            .stderr(err.clone())
+            .build();
+
+        lox.eval("print 1; print 2;").unwrap();
+        assert_eq!(out.take(), "1\n2\n");
+
+        let source = "print -nil;";
+        let e = lox.eval(source).unwrap_err();
+        lox.report("main.lox", source, &e).unwrap();
+        assert_eq!(out.contents(), "");
+        assert!(err
+            .contents()
+            .starts_with("error[E0301]: Operand must be a number."));
     }
 }
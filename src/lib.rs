@@ -1,5 +1,11 @@
+pub mod diagnostic;
+pub use crate::diagnostic::Diagnostic;
+
 pub mod lox_error;
-pub use crate::lox_error::LoxError;
+pub use crate::lox_error::{EvalError, LoxError, RuntimeError, Thrown};
+
+pub mod span;
+pub use crate::span::Span;
 
 pub mod tokens;
 pub use crate::tokens::Token;
@@ -7,6 +13,9 @@ pub use crate::tokens::Token;
 pub mod object;
 pub use crate::object::Object;
 
+pub mod convert;
+pub use crate::convert::{FromLox, FromLoxArgs, IntoLox, IntoLoxArgs};
+
 pub mod scanner;
 pub use crate::scanner::Scanner;
 
@@ -17,7 +26,7 @@ pub mod parser;
 pub use crate::parser::Parser;
 
 pub mod interpreter;
-pub use crate::interpreter::Interpreter;
+pub use crate::interpreter::{Interpreter, InterpreterBuilder};
 
 pub mod statements;
 pub use crate::statements::Statement;
@@ -26,10 +35,29 @@ pub mod environment;
 pub use crate::environment::Environment;
 
 pub mod callable;
-pub use crate::callable::Callable;
+pub use crate::callable::{Args, Callable, NativeFunction};
 
 pub mod resolver;
 pub use crate::resolver::Resolver;
 
 pub mod class;
 pub use crate::class::LoxClass;
+
+pub mod output;
+pub use crate::output::OutputBuffer;
+
+pub mod test_runner;
+
+pub mod native_class;
+pub use crate::native_class::NativeClass;
+
+pub mod list;
+pub use crate::list::LoxList;
+
+pub mod map;
+pub use crate::map::LoxMap;
+
+pub mod module;
+pub use crate::module::LoxModule;
+
+pub mod string;
@@ -0,0 +1,152 @@
+use crate::callable::{Args, NativeFunction};
+use crate::diagnostic::{Diagnostic, ErrorCode};
+use crate::tokens::Token;
+use crate::{LoxError, Object};
+
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+/// A growable list. Clones share the same elements, like instances do.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LoxList {
+    items: Rc<RefCell<Vec<Object>>>,
+}
+
+impl LoxList {
+    pub fn new(items: Vec<Object>) -> Self {
+        Self {
+            items: Rc::new(RefCell::new(items)),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.borrow().is_empty()
+    }
+
+    pub fn to_vec(&self) -> Vec<Object> {
+        self.items.borrow().clone()
+    }
+
+    /// Whether both refer to the same list.
+    pub fn is(&self, other: &LoxList) -> bool {
+        Rc::ptr_eq(&self.items, &other.items)
+    }
+
+    pub fn get(&self, index: &Object) -> Result<Object, LoxError> {
+        let i = self.position(index, self.len())?;
+        Ok(self.items.borrow()[i].clone())
+    }
+
+    pub fn set(&self, index: &Object, value: Object) -> Result<(), LoxError> {
+        let i = self.position(index, self.len())?;
+        self.items.borrow_mut()[i] = value;
+        Ok(())
+    }
+
+    /// Returns the built-in method `name` bound to this list.
+    pub fn method(&self, name: &Token) -> Result<Object, LoxError> {
+        let list = self.clone();
+        let method = match name.lexeme.as_str() {
+            "len" => NativeFunction::new("len", 0, move |_| Ok(list.len())),
+            "push" => NativeFunction::new("push", 1, move |args| {
+                list.items.borrow_mut().push(args[0].clone());
+                Ok(Object::Nil)
+            }),
+            "pop" => NativeFunction::new("pop", 0, move |_| {
+                list.items.borrow_mut().pop().ok_or_else(|| {
+                    LoxError::new(ErrorCode::IndexOutOfRange, "Can't pop from an empty list.")
+                })
+            }),
+            "insert" => NativeFunction::new("insert", 2, move |args| {
+                let i = list.position(&args[0], list.len() + 1)?;
+                list.items.borrow_mut().insert(i, args[1].clone());
+                Ok(Object::Nil)
+            }),
+            "remove" => NativeFunction::new("remove", 1, move |args| {
+                let i = list.position(&args[0], list.len())?;
+                Ok(list.items.borrow_mut().remove(i))
+            }),
+            "slice" => NativeFunction::variadic("slice", 1, move |args| list.slice(args)),
+            _ => {
+                return Err(Diagnostic::error(
+                    ErrorCode::UndefinedProperty,
+                    format!("Undefined property '{}'.", name.lexeme),
+                )
+                .with_span(name.span)
+                .into())
+            }
+        };
+        Ok(Object::Callable(Rc::new(Box::new(method))))
+    }
+
+    // `slice(start)` or `slice(start, end)`, end exclusive.
+    fn slice(&self, args: &Args) -> Result<LoxList, LoxError> {
+        if args.len() > 2 {
+            return Err(LoxError::new(
+                ErrorCode::ArityMismatch,
+                format!("Expected at most 2 arguments but got {}.", args.len()),
+            ));
+        }
+        let len = self.len();
+        let end = match args.get(1) {
+            Some(end) => self.position(end, len + 1)?,
+            None => len,
+        };
+        let start = self.position(&args[0], len + 1)?;
+        if start > end {
+            return Err(Diagnostic::error(
+                ErrorCode::IndexOutOfRange,
+                format!("Range {}..{} is out of range.", start, end),
+            )
+            .with_note(format!("the list has length {}", len))
+            .into());
+        }
+        Ok(LoxList::new(self.items.borrow()[start..end].to_vec()))
+    }
+
+    // Checks that `index` is a whole number below `bound`.
+    fn position(&self, index: &Object, bound: usize) -> Result<usize, LoxError> {
+        let n = match index {
+            Object::Number(n) if n.fract() == 0.0 => *n,
+            _ => {
+                return Err(LoxError::new(
+                    ErrorCode::TypeMismatch,
+                    format!("List index must be a whole number, not {}.", index.repr()),
+                ))
+            }
+        };
+        if n < 0.0 || n >= bound as f64 {
+            return Err(Diagnostic::error(
+                ErrorCode::IndexOutOfRange,
+                format!("Index {} is out of range.", n),
+            )
+            .with_note(format!("the list has length {}", self.len()))
+            .into());
+        }
+        Ok(n as usize)
+    }
+}
+
+thread_local! {
+    // Lists currently being printed, so one that contains itself prints as
+    // `[...]` instead of recursing forever.
+    static PRINTING: RefCell<Vec<*const RefCell<Vec<Object>>>> = const { RefCell::new(Vec::new()) };
+}
+
+impl fmt::Display for LoxList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ptr = Rc::as_ptr(&self.items);
+        if PRINTING.with(|p| p.borrow().contains(&ptr)) {
+            return write!(f, "[...]");
+        }
+        PRINTING.with(|p| p.borrow_mut().push(ptr));
+        let items: Vec<String> = self.items.borrow().iter().map(Object::repr).collect();
+        PRINTING.with(|p| p.borrow_mut().pop());
+        write!(f, "[{}]", items.join(", "))
+    }
+}
@@ -1,14 +1,246 @@
+use crate::diagnostic::{Diagnostic, ErrorCode};
 use crate::object::Object;
+use crate::span::Span;
+use std::fmt;
+use std::rc::Rc;
 
 #[derive(Debug)]
 pub enum LoxError {
     IoError(std::io::Error),
-    Error(String),
-    ParsingError(String),
-    UndefinedVariable(String),
-    TokenListEmpty,
-    NotExpression,
+    Diagnostic(Box<Diagnostic>),
+    Runtime(Box<RuntimeError>),
     Return(Object),
+    Break,
+    Continue,
+    Throw(Box<Thrown>),
+}
+
+/// A value raised by `throw`, along with the error reported if no `catch`
+/// handles it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Thrown {
+    pub value: Object,
+    pub error: RuntimeError,
+}
+
+/// An imported file, kept to render errors whose spans point into it.
+#[derive(Debug, PartialEq)]
+pub struct SourceFile {
+    pub name: String,
+    pub source: String,
+}
+
+/// A call that was active when a runtime error occurred.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub function: String,
+    /// Set when `function` is a method.
+    pub class: Option<String>,
+    pub call_site: Span,
+    /// The module containing `call_site`, `None` for the main script.
+    pub file: Option<Rc<SourceFile>>,
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.class {
+            Some(class) => write!(f, "{}.{}", class, self.function),
+            None => write!(f, "{}", self.function),
+        }
+    }
+}
+
+/// An error raised while executing a program, with the Lox-level call
+/// stack that led to it, innermost call first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub diagnostic: Box<Diagnostic>,
+    pub trace: Vec<Frame>,
+    /// The module the diagnostic points into, `None` for the main script.
+    pub file: Option<Rc<SourceFile>>,
+}
+
+impl RuntimeError {
+    pub fn new(diagnostic: Diagnostic) -> Self {
+        Self {
+            diagnostic: Box::new(diagnostic),
+            trace: Vec::new(),
+            file: None,
+        }
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        self.diagnostic.span()
+    }
+
+    /// Where the error surfaced in the main script: its own span, or for
+    /// an error raised in a module, the call that led into the module.
+    pub fn script_span(&self) -> Option<Span> {
+        if self.file.is_none() {
+            return self.span();
+        }
+        self.trace
+            .iter()
+            .find(|frame| frame.file.is_none())
+            .map(|frame| frame.call_site)
+    }
+
+    /// Formats the trace as one `at Class.method (file:line:col)` line per
+    /// active call, each pointing at where execution was inside that call.
+    pub fn backtrace(&self, file_name: &str) -> String {
+        self.format_trace(|function, file, span| {
+            let file = file.map_or(file_name, |file| &file.name);
+            format!("  at {} ({}:{})\n", function, file, span)
+        })
+    }
+
+    /// The trace as stored in the `stack` field of a caught error, with
+    /// `at Class.method (line:col)` lines and no file name.
+    pub fn stack(&self) -> String {
+        self.format_trace(|function, _, span| format!("at {} ({})\n", function, span))
+            .trim_end()
+            .to_string()
+    }
+
+    fn format_trace(&self, line: impl Fn(&str, Option<&SourceFile>, Span) -> String) -> String {
+        let functions = self
+            .trace
+            .iter()
+            .map(|f| f.to_string())
+            .chain(std::iter::once("<script>".to_string()));
+        // Calls made by the host have no call site and end the trace.
+        let locations = self
+            .span()
+            .map(|span| (self.file.as_deref(), span))
+            .into_iter()
+            .chain(self.trace.iter().map(|f| (f.file.as_deref(), f.call_site)))
+            .take_while(|(_, span)| span.line >= 1);
+
+        let mut out = String::new();
+        for (function, (file, span)) in functions.zip(locations) {
+            out += &line(&function, file, span);
+        }
+        out
+    }
+
+    /// Renders the diagnostic followed by its backtrace. `source` is the
+    /// main script; modules the error passed through bring their own.
+    pub fn render(&self, file_name: &str, source: &str) -> String {
+        let diagnostic = match &self.file {
+            Some(file) => self.diagnostic.render(&file.name, &file.source),
+            None => self.diagnostic.render(file_name, source),
+        };
+        diagnostic + &self.backtrace(file_name)
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.diagnostic)
+    }
+}
+
+impl From<LoxError> for RuntimeError {
+    fn from(err: LoxError) -> Self {
+        match err {
+            LoxError::Runtime(r) => *r,
+            LoxError::Diagnostic(d) => RuntimeError {
+                diagnostic: d,
+                trace: Vec::new(),
+                file: None,
+            },
+            LoxError::IoError(e) => {
+                RuntimeError::new(Diagnostic::error(ErrorCode::Io, e.to_string()))
+            }
+            LoxError::Return(_) => RuntimeError::new(Diagnostic::error(
+                ErrorCode::TopLevelReturn,
+                "Can't return from top-level code.",
+            )),
+            LoxError::Throw(thrown) => thrown.error,
+            LoxError::Break | LoxError::Continue => RuntimeError::new(Diagnostic::error(
+                ErrorCode::OutsideLoop,
+                "Can't use 'break' or 'continue' outside of a loop.",
+            )),
+        }
+    }
+}
+
+/// Everything that can go wrong when evaluating a source string.
+#[derive(Debug)]
+pub enum EvalError {
+    Compile(Vec<Diagnostic>),
+    Runtime(RuntimeError),
+}
+
+impl EvalError {
+    pub fn render(&self, file_name: &str, source: &str) -> String {
+        match self {
+            EvalError::Compile(diagnostics) => diagnostics
+                .iter()
+                .map(|d| d.render(file_name, source))
+                .collect(),
+            EvalError::Runtime(e) => e.render(file_name, source),
+        }
+    }
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EvalError::Compile(diagnostics) => {
+                let messages: Vec<String> = diagnostics.iter().map(|d| d.to_string()).collect();
+                write!(f, "{}", messages.join("\n"))
+            }
+            EvalError::Runtime(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<Vec<Diagnostic>> for EvalError {
+    fn from(diagnostics: Vec<Diagnostic>) -> Self {
+        EvalError::Compile(diagnostics)
+    }
+}
+
+impl From<RuntimeError> for EvalError {
+    fn from(err: RuntimeError) -> Self {
+        EvalError::Runtime(err)
+    }
+}
+
+impl LoxError {
+    pub fn new<T>(code: ErrorCode, message: T) -> Self
+    where
+        T: Into<String>,
+    {
+        Diagnostic::error(code, message).into()
+    }
+
+    /// Attaches `span` to a diagnostic that does not have a location yet.
+    pub fn or_span(self, span: Span) -> Self {
+        match self {
+            LoxError::Diagnostic(d) => LoxError::Diagnostic(Box::new(d.or_span(span))),
+            LoxError::Runtime(mut r) => {
+                r.diagnostic = Box::new(r.diagnostic.or_span(span));
+                LoxError::Runtime(r)
+            }
+            e => e,
+        }
+    }
+}
+
+impl fmt::Display for LoxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoxError::IoError(e) => write!(f, "error: {}", e),
+            LoxError::Diagnostic(d) => write!(f, "{}", d),
+            LoxError::Runtime(r) => write!(f, "{}", r),
+            LoxError::Return(o) => write!(f, "return {}", o),
+            LoxError::Break => write!(f, "break"),
+            LoxError::Continue => write!(f, "continue"),
+            LoxError::Throw(thrown) => write!(f, "{}", thrown.error),
+        }
+    }
 }
 
 impl From<std::io::Error> for LoxError {
@@ -16,3 +248,9 @@ impl From<std::io::Error> for LoxError {
         LoxError::IoError(err)
     }
 }
+
+impl From<Diagnostic> for LoxError {
+    fn from(diagnostic: Diagnostic) -> Self {
+        LoxError::Diagnostic(Box::new(diagnostic))
+    }
+}
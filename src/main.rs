@@ -1,21 +1,26 @@
-use std::io::{self, Write};
-use rlox::parser::Parser;
-use rlox::scanner::Scanner;
+use rlox::test_runner;
 use rlox::Interpreter;
-use rlox::LoxError;
+use rlox::{EvalError, LoxError};
+use std::io::{self, Write};
+use std::path::Path;
 
-fn main() -> Result<(), LoxError> {
+fn main() {
     let args: Vec<String> = std::env::args().collect();
-    match args.len() {
-        1 => run_prompt()?,
-        2 => run_file(args.get(1).unwrap())?,
+    let res = match args.len() {
+        1 => run_prompt(),
+        2 => run_file(args.get(1).unwrap()),
+        3 if args[1] == "test" => run_tests(&args[2]),
         _ => {
             println!("Usage: rlox [script]");
+            println!("       rlox test <dir>");
             std::process::exit(64);
         }
-    }
+    };
 
-    Ok(())
+    if let Err(e) = res {
+        eprintln!("{}", e);
+        std::process::exit(74);
+    }
 }
 
 fn run_prompt() -> Result<(), LoxError> {
@@ -31,28 +36,40 @@ fn run_prompt() -> Result<(), LoxError> {
             break;
         }
     }
-    run(code)?;
+    run("<stdin>", code);
     Ok(())
 }
 
 fn run_file(path: &str) -> Result<(), LoxError> {
     let input = std::fs::read_to_string(path)?;
-    run(input)?;
+    run(path, input);
 
     Ok(())
 }
 
-fn run(input: String) -> Result<(), LoxError> {
-    let mut scn = Scanner::new(input);
-    scn.scan_tokens()?;
-
-    let tokens = scn.get_tokens();
-
-    let mut parser = Parser::new(tokens);
-
-    let statements = parser.parse()?;
-
-    Interpreter::interpret(statements)?;
+fn run_tests(dir: &str) -> Result<(), LoxError> {
+    let report = test_runner::run_dir(Path::new(dir))?;
+    for (path, failures) in &report.failures {
+        println!("FAIL {}", path.display());
+        for failure in failures {
+            println!("     {}", failure);
+        }
+    }
+    println!("{} passed, {} failed", report.passed, report.failures.len());
 
+    if !report.is_success() {
+        std::process::exit(1);
+    }
     Ok(())
 }
+
+fn run(file_name: &str, input: String) {
+    let mut interpreter = Interpreter::builder().build();
+    if let Err(e) = interpreter.eval_script(file_name, &input) {
+        let _ = interpreter.report(file_name, &input, &e);
+        match e {
+            EvalError::Compile(_) => std::process::exit(65),
+            EvalError::Runtime(_) => std::process::exit(70),
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use crate::callable::NativeFunction;
+use crate::diagnostic::{Diagnostic, ErrorCode};
+use crate::tokens::Token;
+use crate::{LoxError, LoxList, Object};
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+/// A value that can be used as a map key. Two keys are the same exactly when
+/// `Object::is_equal` says their values are, so `0` and `-0` collide and NaN
+/// is rejected, since it isn't equal to itself.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum MapKey {
+    Nil,
+    Boolean(bool),
+    Number(u64),
+    String(String),
+}
+
+impl MapKey {
+    fn new(key: &Object) -> Result<Self, LoxError> {
+        match key {
+            Object::Nil => Ok(MapKey::Nil),
+            Object::Boolean(b) => Ok(MapKey::Boolean(*b)),
+            Object::Number(n) if n.is_nan() => Err(LoxError::new(
+                ErrorCode::TypeMismatch,
+                "Map keys can't be NaN.",
+            )),
+            // Adding 0.0 turns -0.0 into 0.0
+            Object::Number(n) => Ok(MapKey::Number((n + 0.0).to_bits())),
+            Object::String(s) => Ok(MapKey::String(s.clone())),
+            _ => Err(Diagnostic::error(
+                ErrorCode::TypeMismatch,
+                format!("{} can't be used as a map key.", key.repr()),
+            )
+            .with_note("keys must be strings, numbers, booleans or nil")
+            .into()),
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Entries {
+    // In insertion order
+    entries: Vec<(Object, Object)>,
+    index: HashMap<MapKey, usize>,
+}
+
+/// A map from strings, numbers, booleans and nil to values. Iterates in
+/// insertion order, and clones share the same entries.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LoxMap {
+    inner: Rc<RefCell<Entries>>,
+}
+
+impl LoxMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.borrow().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.borrow().entries.is_empty()
+    }
+
+    /// Whether both refer to the same map.
+    pub fn is(&self, other: &LoxMap) -> bool {
+        Rc::ptr_eq(&self.inner, &other.inner)
+    }
+
+    pub fn entries(&self) -> Vec<(Object, Object)> {
+        self.inner.borrow().entries.clone()
+    }
+
+    pub fn keys(&self) -> Vec<Object> {
+        let inner = self.inner.borrow();
+        inner.entries.iter().map(|(k, _)| k.clone()).collect()
+    }
+
+    pub fn values(&self) -> Vec<Object> {
+        let inner = self.inner.borrow();
+        inner.entries.iter().map(|(_, v)| v.clone()).collect()
+    }
+
+    pub fn has(&self, key: &Object) -> Result<bool, LoxError> {
+        let key = MapKey::new(key)?;
+        Ok(self.inner.borrow().index.contains_key(&key))
+    }
+
+    /// Looks up `key`, failing if it isn't in the map.
+    pub fn get(&self, key: &Object) -> Result<Object, LoxError> {
+        let inner = self.inner.borrow();
+        match inner.index.get(&MapKey::new(key)?) {
+            Some(&i) => Ok(inner.entries[i].1.clone()),
+            None => Err(Diagnostic::error(
+                ErrorCode::UndefinedKey,
+                format!("Undefined key {}.", key.repr()),
+            )
+            .with_note("use 'has' to check whether a key exists")
+            .into()),
+        }
+    }
+
+    /// Inserts or replaces the value for `key`.
+    pub fn set(&self, key: Object, value: Object) -> Result<(), LoxError> {
+        let map_key = MapKey::new(&key)?;
+        let mut inner = self.inner.borrow_mut();
+        match inner.index.get(&map_key) {
+            Some(&i) => inner.entries[i].1 = value,
+            None => {
+                let i = inner.entries.len();
+                inner.index.insert(map_key, i);
+                inner.entries.push((key, value));
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes `key`, returning its value if it was present.
+    pub fn remove(&self, key: &Object) -> Result<Option<Object>, LoxError> {
+        let map_key = MapKey::new(key)?;
+        let mut inner = self.inner.borrow_mut();
+        let Some(i) = inner.index.remove(&map_key) else {
+            return Ok(None);
+        };
+        let (_, value) = inner.entries.remove(i);
+        for position in inner.index.values_mut() {
+            if *position > i {
+                *position -= 1;
+            }
+        }
+        Ok(Some(value))
+    }
+
+    /// Returns the built-in method `name` bound to this map.
+    pub fn method(&self, name: &Token) -> Result<Object, LoxError> {
+        let map = self.clone();
+        let method = match name.lexeme.as_str() {
+            "len" => NativeFunction::new("len", 0, move |_| Ok(map.len())),
+            "keys" => NativeFunction::new("keys", 0, move |_| Ok(LoxList::new(map.keys()))),
+            "values" => NativeFunction::new("values", 0, move |_| Ok(LoxList::new(map.values()))),
+            "has" => NativeFunction::new("has", 1, move |args| map.has(&args[0])),
+            "remove" => NativeFunction::new("remove", 1, move |args| map.remove(&args[0])),
+            _ => {
+                return Err(Diagnostic::error(
+                    ErrorCode::UndefinedProperty,
+                    format!("Undefined property '{}'.", name.lexeme),
+                )
+                .with_span(name.span)
+                .into())
+            }
+        };
+        Ok(Object::Callable(Rc::new(Box::new(method))))
+    }
+}
+
+thread_local! {
+    // Maps currently being printed, see the same guard for lists.
+    static PRINTING: RefCell<Vec<*const RefCell<Entries>>> = const { RefCell::new(Vec::new()) };
+}
+
+impl fmt::Display for LoxMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ptr = Rc::as_ptr(&self.inner);
+        if PRINTING.with(|p| p.borrow().contains(&ptr)) {
+            return write!(f, "{{...}}");
+        }
+        PRINTING.with(|p| p.borrow_mut().push(ptr));
+        let entries: Vec<String> = self
+            .inner
+            .borrow()
+            .entries
+            .iter()
+            .map(|(k, v)| format!("{}: {}", k.repr(), v.repr()))
+            .collect();
+        PRINTING.with(|p| p.borrow_mut().pop());
+        write!(f, "{{{}}}", entries.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_follow_is_equal() {
+        let map = LoxMap::new();
+        map.set(Object::Number(0.0), Object::Boolean(true)).unwrap();
+        map.set(Object::String("a".to_string()), Object::Nil)
+            .unwrap();
+        assert_eq!(
+            map.get(&Object::Number(-0.0)).unwrap(),
+            Object::Boolean(true)
+        );
+        assert!(!map.has(&Object::String("0".to_string())).unwrap());
+        assert!(map.set(Object::Number(f64::NAN), Object::Nil).is_err());
+        assert!(map.has(&Object::List(LoxList::default())).is_err());
+
+        map.set(Object::Nil, Object::Number(1.0)).unwrap();
+        let removed = map.remove(&Object::Number(0.0)).unwrap();
+        assert_eq!(removed, Some(Object::Boolean(true)));
+        assert_eq!(map.get(&Object::Nil).unwrap(), Object::Number(1.0));
+        assert_eq!(map.to_string(), "{\"a\": nil, nil: 1}");
+    }
+}
@@ -0,0 +1,67 @@
+use crate::diagnostic::{Diagnostic, ErrorCode};
+use crate::environment::Environment;
+use crate::tokens::Token;
+use crate::{LoxError, Object};
+
+use std::fmt;
+use std::rc::Rc;
+
+/// A `.lox` file loaded by `import`. Its top-level definitions are read as
+/// properties, like `m.name`.
+#[derive(Debug, Clone)]
+pub struct LoxModule {
+    inner: Rc<Module>,
+}
+
+#[derive(Debug)]
+struct Module {
+    name: String,
+    globals: Environment,
+}
+
+impl LoxModule {
+    pub(crate) fn new(name: impl Into<String>, globals: Environment) -> Self {
+        Self {
+            inner: Rc::new(Module {
+                name: name.into(),
+                globals,
+            }),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.inner.name
+    }
+
+    /// Looks up a top-level definition.
+    pub fn get(&self, name: &Token) -> Result<Object, LoxError> {
+        self.inner.globals.get_local(&name.lexeme).ok_or_else(|| {
+            Diagnostic::error(
+                ErrorCode::UndefinedProperty,
+                format!(
+                    "Module '{}' doesn't define '{}'.",
+                    self.inner.name, name.lexeme
+                ),
+            )
+            .with_span(name.span)
+            .into()
+        })
+    }
+
+    /// Whether both refer to the same module.
+    pub fn is(&self, other: &LoxModule) -> bool {
+        Rc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+impl PartialEq for LoxModule {
+    fn eq(&self, other: &Self) -> bool {
+        self.is(other)
+    }
+}
+
+impl fmt::Display for LoxModule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<module {}>", self.inner.name)
+    }
+}
@@ -0,0 +1,330 @@
+use crate::callable::{Args, Callable};
+use crate::class::{LoxClass, LoxInstance};
+use crate::convert::{FromLox, IntoLox};
+use crate::diagnostic::{Diagnostic, ErrorCode};
+use crate::interpreter::Interpreter;
+use crate::{LoxError, Object};
+
+use std::collections::HashMap;
+use std::fmt;
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+type MethodFn = dyn Fn(&LoxInstance, &Args) -> Result<Object, LoxError>;
+type GetterFn = dyn Fn(&LoxInstance) -> Result<Object, LoxError>;
+type SetterFn = dyn Fn(&LoxInstance, &Object) -> Result<(), LoxError>;
+
+/// Describes a class implemented in Rust whose instances wrap a `T`.
+///
+/// Scripts use the built class like any other: they can construct it, call
+/// its methods, read and write its properties and inherit from it.
+///
+/// ```
+/// # use rlox::{Interpreter, NativeClass, Object};
+/// struct Counter(f64);
+///
+/// let counter = NativeClass::new("Counter")
+///     .init(1, |args| Ok(Counter(args.num(0)?)))
+///     .method("bump", 0, |c: &mut Counter, _| {
+///         c.0 += 1.0;
+///         Ok(c.0)
+///     })
+///     .getter("count", |c: &Counter| c.0);
+///
+/// let mut lox = Interpreter::builder().build();
+/// lox.define_class(counter);
+/// lox.eval("var c = Counter(41); c.bump();").unwrap();
+/// assert_eq!(lox.eval("c.count;").unwrap(), Object::Number(42.0));
+/// ```
+pub struct NativeClass<T> {
+    name: String,
+    methods: HashMap<String, NativeMethod>,
+    properties: HashMap<String, NativeProperty>,
+    marker: PhantomData<T>,
+}
+
+impl<T: 'static> NativeClass<T> {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            methods: HashMap::new(),
+            properties: HashMap::new(),
+            marker: PhantomData,
+        }
+    }
+
+    /// Sets the constructor. It runs when the class, or a subclass without
+    /// its own `init`, is called, and via `super.init(...)`.
+    pub fn init<F>(self, arity: usize, init: F) -> Self
+    where
+        F: Fn(&Args) -> Result<T, LoxError> + 'static,
+    {
+        self.insert_method("init", arity, move |this, args| {
+            this.set_native(init(args)?);
+            Ok(Object::Instance(this.clone()))
+        })
+    }
+
+    pub fn method<F, R>(self, name: &str, arity: usize, method: F) -> Self
+    where
+        F: Fn(&mut T, &Args) -> Result<R, LoxError> + 'static,
+        R: IntoLox,
+    {
+        let class = self.name.clone();
+        self.insert_method(name, arity, move |this, args| {
+            Self::with_native(&class, this, |value| method(value, args))?.map(IntoLox::into_lox)
+        })
+    }
+
+    /// Exposes a read-only property. Add a `setter` to make it writable.
+    pub fn getter<F, R>(mut self, name: &str, getter: F) -> Self
+    where
+        F: Fn(&T) -> R + 'static,
+        R: IntoLox,
+    {
+        let class = self.name.clone();
+        self.properties.entry(name.to_string()).or_default().get = Some(Rc::new(move |this| {
+            Self::with_native(&class, this, |value| getter(value).into_lox())
+        }));
+        self
+    }
+
+    pub fn setter<F, V>(mut self, name: &str, setter: F) -> Self
+    where
+        F: Fn(&mut T, V) + 'static,
+        V: FromLox,
+    {
+        let class = self.name.clone();
+        self.properties.entry(name.to_string()).or_default().set =
+            Some(Rc::new(move |this, value| {
+                let value = V::from_lox(value)?;
+                Self::with_native(&class, this, |native| setter(native, value))
+            }));
+        self
+    }
+
+    pub fn build(self) -> LoxClass {
+        LoxClass::native(self.name, self.methods, self.properties)
+    }
+
+    fn insert_method<F>(mut self, name: &str, arity: usize, function: F) -> Self
+    where
+        F: Fn(&LoxInstance, &Args) -> Result<Object, LoxError> + 'static,
+    {
+        let method = NativeMethod {
+            name: name.to_string(),
+            class: self.name.clone(),
+            arity,
+            function: Rc::new(function),
+        };
+        self.methods.insert(name.to_string(), method);
+        self
+    }
+
+    fn with_native<R>(
+        class: &str,
+        this: &LoxInstance,
+        f: impl FnOnce(&mut T) -> R,
+    ) -> Result<R, LoxError> {
+        this.with_native(f).ok_or_else(|| {
+            Diagnostic::error(
+                ErrorCode::UninitializedNative,
+                format!("Instance was not initialized by native class '{}'.", class),
+            )
+            .with_note("a subclass's init() must call super.init(...)")
+            .into()
+        })
+    }
+}
+
+/// The built-in `Error` class. `Error(message)` stores `message`; `stack`
+/// is filled in when the error is thrown.
+pub(crate) fn error_class() -> LoxClass {
+    NativeClass::<()>::new("Error")
+        .insert_method("init", 1, |this, args| {
+            this.set_field("message", args[0].clone());
+            this.set_field("stack", Object::Nil);
+            Ok(Object::Instance(this.clone()))
+        })
+        .build()
+}
+
+/// A method of a `NativeClass`, before it is bound to an instance.
+#[derive(Clone)]
+pub struct NativeMethod {
+    name: String,
+    class: String,
+    arity: usize,
+    function: Rc<MethodFn>,
+}
+
+impl NativeMethod {
+    pub fn arity(&self) -> usize {
+        self.arity
+    }
+
+    pub fn bind(&self, instance: LoxInstance) -> BoundNativeMethod {
+        BoundNativeMethod {
+            method: self.clone(),
+            this: instance,
+        }
+    }
+}
+
+impl fmt::Debug for NativeMethod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<native method {}.{}>", self.class, self.name)
+    }
+}
+
+impl PartialEq for NativeMethod {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.function, &other.function)
+    }
+}
+
+#[derive(Debug)]
+pub struct BoundNativeMethod {
+    method: NativeMethod,
+    this: LoxInstance,
+}
+
+impl Callable for BoundNativeMethod {
+    fn call(&self, _: &mut Interpreter, args: &[Object]) -> Result<Object, LoxError> {
+        (self.method.function)(&self.this, &Args::new(&self.method.name, args))
+    }
+    fn arity(&self) -> usize {
+        self.method.arity
+    }
+    fn name(&self) -> String {
+        self.method.name.clone()
+    }
+    fn class_name(&self) -> Option<String> {
+        Some(self.method.class.clone())
+    }
+    fn display(&self) -> String {
+        "<native fn>".to_string()
+    }
+}
+
+/// A property of a `NativeClass` backed by Rust accessors.
+#[derive(Clone, Default)]
+pub struct NativeProperty {
+    get: Option<Rc<GetterFn>>,
+    set: Option<Rc<SetterFn>>,
+}
+
+impl NativeProperty {
+    pub fn get(&self, this: &LoxInstance) -> Option<Result<Object, LoxError>> {
+        self.get.as_ref().map(|get| get(this))
+    }
+
+    pub fn set(&self, this: &LoxInstance, value: &Object) -> Option<Result<(), LoxError>> {
+        self.set.as_ref().map(|set| set(this, value))
+    }
+}
+
+impl fmt::Debug for NativeProperty {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("NativeProperty")
+            .field("get", &self.get.is_some())
+            .field("set", &self.set.is_some())
+            .finish()
+    }
+}
+
+impl PartialEq for NativeProperty {
+    fn eq(&self, other: &Self) -> bool {
+        fn same<T: ?Sized>(a: &Option<Rc<T>>, b: &Option<Rc<T>>) -> bool {
+            match (a, b) {
+                (Some(a), Some(b)) => Rc::ptr_eq(a, b),
+                (None, None) => true,
+                _ => false,
+            }
+        }
+        same(&self.get, &other.get) && same(&self.set, &other.set)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EvalError;
+
+    struct Buffer {
+        bytes: Vec<f64>,
+        limit: usize,
+    }
+
+    fn interpreter() -> Interpreter {
+        let buffer = NativeClass::new("Buffer")
+            .init(1, |args| {
+                Ok(Buffer {
+                    bytes: Vec::new(),
+                    limit: args.arg(0)?,
+                })
+            })
+            .method("push", 1, |b: &mut Buffer, args| {
+                b.bytes.push(args.num(0)?);
+                Ok(b.bytes.len())
+            })
+            .getter("len", |b: &Buffer| b.bytes.len())
+            .getter("limit", |b: &Buffer| b.limit)
+            .setter("limit", |b: &mut Buffer, limit: usize| b.limit = limit);
+
+        let mut lox = Interpreter::builder().build();
+        lox.define_class(buffer);
+        lox
+    }
+
+    fn error_code(lox: &mut Interpreter, source: &str) -> ErrorCode {
+        match lox.eval(source) {
+            Err(EvalError::Runtime(e)) => e.diagnostic.code,
+            other => panic!("expected a runtime error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn methods_and_properties() {
+        let mut lox = interpreter();
+        lox.eval("var b = Buffer(4); b.push(1); b.push(2); b.limit = 8; b.tag = \"t\";")
+            .unwrap();
+        assert_eq!(lox.eval("b.len;").unwrap(), Object::Number(2.0));
+        assert_eq!(lox.eval("b.limit;").unwrap(), Object::Number(8.0));
+        assert_eq!(lox.eval("b.tag;").unwrap(), Object::String("t".to_string()));
+
+        assert_eq!(
+            error_code(&mut lox, "b.len = 1;"),
+            ErrorCode::ReadOnlyProperty
+        );
+        assert_eq!(
+            error_code(&mut lox, "b.limit = -1;"),
+            ErrorCode::TypeMismatch
+        );
+    }
+
+    #[test]
+    fn subclasses() {
+        let mut lox = interpreter();
+        lox.eval(
+            "class Lines < Buffer {\n\
+               init() { super.init(2); this.count = 0; }\n\
+               push(x) { this.count = this.count + 1; return super.push(x); }\n\
+             }\n\
+             class Plain < Buffer {}\n\
+             class Broken < Buffer { init() {} }\n\
+             var lines = Lines();\n\
+             lines.push(7);",
+        )
+        .unwrap();
+        assert_eq!(
+            lox.eval("lines.len + lines.count;").unwrap(),
+            Object::Number(2.0)
+        );
+        assert_eq!(lox.eval("Plain(1).push(1);").unwrap(), Object::Number(1.0));
+        assert_eq!(
+            error_code(&mut lox, "Broken().push(1);"),
+            ErrorCode::UninitializedNative
+        );
+    }
+}
@@ -1,12 +1,12 @@
 use crate::callable::Callable;
-use std::cell::RefCell;
 use std::fmt;
 use std::rc::Rc;
 
 use crate::class::LoxInstance;
-use crate::{LoxClass, LoxError};
+use crate::diagnostic::ErrorCode;
+use crate::{LoxClass, LoxError, LoxList, LoxMap, LoxModule};
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, Clone)]
 pub enum Object {
     String(String),
     Number(f64),
@@ -15,19 +15,44 @@ pub enum Object {
     Callable(Rc<Box<dyn Callable>>),
     Instance(LoxInstance),
     Class(LoxClass),
+    List(LoxList),
+    Map(LoxMap),
+    Module(LoxModule),
+}
+
+// Callables are only equal to themselves, like classes.
+impl PartialEq for Object {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Object::String(a), Object::String(b)) => a == b,
+            (Object::Number(a), Object::Number(b)) => a == b,
+            (Object::Boolean(a), Object::Boolean(b)) => a == b,
+            (Object::Nil, Object::Nil) => true,
+            (Object::Callable(a), Object::Callable(b)) => Rc::ptr_eq(a, b),
+            (Object::Instance(a), Object::Instance(b)) => a == b,
+            (Object::Class(a), Object::Class(b)) => a == b,
+            (Object::List(a), Object::List(b)) => a == b,
+            (Object::Map(a), Object::Map(b)) => a == b,
+            (Object::Module(a), Object::Module(b)) => a == b,
+            _ => false,
+        }
+    }
 }
 
 impl fmt::Display for Object {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        return match self {
-            Object::String(s) => write!(f, "\"{}\"", s),
+        match self {
+            Object::String(s) => write!(f, "{}", s),
             Object::Number(n) => write!(f, "{}", n),
             Object::Boolean(b) => write!(f, "{}", b),
-            Object::Nil => write!(f, "NIL"),
+            Object::Nil => write!(f, "nil"),
             Object::Callable(o) => write!(f, "{}", o),
-            Object::Instance(c) => write!(f, "Instance <{:?}>", c),
-            Object::Class(c) => write!(f, "Class <{}>", c.name()),
-        };
+            Object::Instance(c) => write!(f, "{}", c),
+            Object::Class(c) => write!(f, "{}", c.name()),
+            Object::List(l) => write!(f, "{}", l),
+            Object::Map(m) => write!(f, "{}", m),
+            Object::Module(m) => write!(f, "{}", m),
+        }
     }
 }
 
@@ -43,12 +68,26 @@ impl Object {
         true
     }
 
+    /// How the value is shown in diagnostics, with strings quoted.
+    pub fn repr(&self) -> String {
+        match self {
+            Object::String(s) => format!("{:?}", s),
+            o => o.to_string(),
+        }
+    }
+
     pub fn is_equal(a: Object, b: Object) -> bool {
         match (a, b) {
             (Object::Nil, Object::Nil) => true,
             (Object::Boolean(a), Object::Boolean(b)) => a == b,
             (Object::Number(a), Object::Number(b)) => a == b,
             (Object::String(a), Object::String(b)) => a == b,
+            (Object::Callable(a), Object::Callable(b)) => Rc::ptr_eq(&a, &b),
+            (Object::Instance(a), Object::Instance(b)) => a.is(&b),
+            (Object::Class(a), Object::Class(b)) => a.is(&b),
+            (Object::List(a), Object::List(b)) => a.is(&b),
+            (Object::Map(a), Object::Map(b)) => a.is(&b),
+            (Object::Module(a), Object::Module(b)) => a.is(&b),
             _ => false,
         }
     }
@@ -57,7 +96,10 @@ impl Object {
         if let Object::Number(n) = self {
             Ok(*n)
         } else {
-            Err(LoxError::Error(format!("'{:?}' must be a number.", self)))
+            Err(LoxError::new(
+                ErrorCode::TypeMismatch,
+                format!("'{}' must be a number.", self),
+            ))
         }
     }
 
@@ -65,7 +107,10 @@ impl Object {
         if let Object::String(s) = self {
             Ok(s.clone())
         } else {
-            Err(LoxError::Error(format!("'{:?}' must be a string.", self)))
+            Err(LoxError::new(
+                ErrorCode::TypeMismatch,
+                format!("'{}' must be a string.", self),
+            ))
         }
     }
 }
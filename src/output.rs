@@ -0,0 +1,46 @@
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+/// An in-memory sink for script output. Clones share the same buffer, so
+/// one clone can be handed to the interpreter and another kept to read
+/// what was written.
+///
+/// ```
+/// # use rlox::{Interpreter, OutputBuffer};
+/// let out = OutputBuffer::new();
+/// let mut lox = Interpreter::builder().stdout(out.clone()).build();
+/// lox.eval("print 1 + 2;").unwrap();
+/// assert_eq!(out.contents(), "3\n");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct OutputBuffer {
+    bytes: Rc<RefCell<Vec<u8>>>,
+}
+
+impl OutputBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.bytes.borrow()).into_owned()
+    }
+
+    /// Returns the contents and empties the buffer.
+    pub fn take(&self) -> String {
+        let bytes = std::mem::take(&mut *self.bytes.borrow_mut());
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+}
+
+impl Write for OutputBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.bytes.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
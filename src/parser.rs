@@ -1,103 +1,236 @@
 use std::collections::VecDeque;
 
-use crate::expressions::{Expr, Var};
+use crate::diagnostic::{Diagnostic, ErrorCode};
+use crate::expressions::{Expr, ExprKind, Var};
 use crate::lox_error::LoxError;
 use crate::object::Object;
-use crate::statements::Statement;
+use crate::span::Span;
+use crate::statements::{Imports, Statement, StatementKind};
 use crate::tokens::{Token, TokenType};
 
+// Deeper nesting is reported instead of overflowing the stack, here or in
+// the passes that walk the tree afterwards. A level can take tens of KiB
+// of stack in a debug build, and code nested this deep still parses and
+// runs on a 2 MiB thread, the default for spawned threads.
+const MAX_NESTING: usize = 16;
+
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    errors: Vec<Diagnostic>,
+    // Number of blocks the parser is currently inside of
+    depth: usize,
+    // Nested statements and subexpressions being parsed, see `nested`
+    nesting: usize,
 }
 
 impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, current: 0 }
-    }
-
-    pub fn parse(&mut self) -> Result<Vec<Statement>, LoxError> {
-        self.statements()
+    pub fn new(mut tokens: Vec<Token>) -> Self {
+        if tokens.last().map(|t| &t.token_type) != Some(&TokenType::EOF) {
+            let span = tokens.last().map(|t| t.span).unwrap_or_default();
+            let end = Span::new(span.end, span.end, span.line, span.column);
+            tokens.push(Token::new(TokenType::EOF, "", None, end));
+        }
+        Self {
+            tokens,
+            current: 0,
+            errors: Vec::new(),
+            depth: 0,
+            nesting: 0,
+        }
     }
 
-    fn statements(&mut self) -> Result<Vec<Statement>, LoxError> {
+    /// Parses all declarations in the token stream.
+    ///
+    /// On a syntax error the parser reports it, skips ahead to the next
+    /// statement boundary and carries on, so every error in the input is
+    /// returned at once.
+    pub fn parse(&mut self) -> Result<Vec<Statement>, Vec<Diagnostic>> {
         let mut statements: Vec<Statement> = Vec::new();
 
-        while !self.is_at_end() && !self.is_match(TokenType::EOF) {
-            statements.push(self.declaration()?);
+        while !self.is_at_end() {
+            if let Some(stmt) = self.declaration() {
+                statements.push(stmt);
+            }
         }
 
-        Ok(statements)
+        if self.errors.is_empty() {
+            Ok(statements)
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
     }
 
-    fn declaration(&mut self) -> Result<Statement, LoxError> {
-        let try_ = {
-            if self.is_match(TokenType::VAR) {
-                self.var_declaration()
-            } else if self.is_match(TokenType::FUN) {
-                self.function("function".to_string())
-            } else {
-                self.statement()
+    fn declaration(&mut self) -> Option<Statement> {
+        match self.try_declaration() {
+            Ok(stmt) => Some(stmt),
+            Err(e) => {
+                self.error(e);
+                self.synchronize();
+                None
             }
-        };
+        }
+    }
 
-        match try_ {
-            Ok(stmt) => Ok(stmt),
-            _ => {
-                println!("Sync: {:?}, {:?}", try_, self.tokens[self.current]);
-                self.synchronize()?;
-                self.declaration()
-            }
+    fn try_declaration(&mut self) -> Result<Statement, LoxError> {
+        if self.is_match(TokenType::IMPORT) {
+            self.import_declaration()
+        } else if self.check_word("from") && self.check_next(TokenType::STRING) {
+            self.advance();
+            self.names_import_declaration()
+        } else if self.is_match(TokenType::VAR) {
+            self.var_declaration()
+        } else if self.check(TokenType::FUN) && !self.check_next(TokenType::LEFT_PAREN) {
+            let start = self.advance().span;
+            self.function("function".to_string(), start)
+        } else {
+            self.nested(Self::statement)
         }
     }
 
-    fn function(&mut self, kind: String) -> Result<Statement, LoxError> {
-        let name = self.consume(TokenType::IDENTIFIER, &format!("Expect {} name.", kind));
+    fn function(&mut self, kind: String, start: Span) -> Result<Statement, LoxError> {
+        let name = self.consume(TokenType::IDENTIFIER, &format!("Expect {} name.", kind))?;
 
         self.consume(
             TokenType::LEFT_PAREN,
             &format!("Expect '(' after {} name.", kind),
-        );
+        )?;
+        let parameters = self.parameters()?;
+
+        self.consume(
+            TokenType::LEFT_BRACE,
+            &format!("Expect '{{' before {} body.", kind),
+        )?;
+        let body = self.block_statement()?;
+
+        Ok(Statement::new(
+            StatementKind::FuncDecl(name, parameters, Box::new(body)),
+            self.span_from(start),
+        ))
+    }
+
+    // Parses a parameter list up to and including the closing ')'.
+    fn parameters(&mut self) -> Result<Vec<Token>, LoxError> {
         let mut parameters = Vec::new();
         if !self.check(TokenType::RIGHT_PAREN) {
             loop {
-                if parameters.len() >= 255 {
-                    return Err(LoxError::Error(
-                        "Can't have more than 255 parameters".to_string(),
-                    ));
+                if parameters.len() == 255 {
+                    let err = Diagnostic::error(
+                        ErrorCode::TooManyParameters,
+                        "Can't have more than 255 parameters.",
+                    )
+                    .with_span(self.peek().span);
+                    self.error(err.into());
                 }
-                parameters.push(self.consume(TokenType::IDENTIFIER, "Expect parameter name."));
+                parameters.push(self.consume(TokenType::IDENTIFIER, "Expect parameter name.")?);
 
                 if !self.is_match(TokenType::COMMA) {
                     break;
                 }
             }
         }
-        self.consume(TokenType::RIGHT_PAREN, "Expect ')' after parameters");
+        self.consume(TokenType::RIGHT_PAREN, "Expect ')' after parameters.")?;
+        Ok(parameters)
+    }
 
-        self.consume(
-            TokenType::LEFT_BRACE,
-            &format!("Expect '{{' before {} body.", kind),
-        );
-        let body = self.block_statement()?;
+    // `fun (a) { ... }`, or `(a) => ...` whose body is a block or a single
+    // returned expression.
+    fn lambda(&mut self, start: Span, arrow: bool) -> Result<Expr, LoxError> {
+        let parameters = self.parameters()?;
+        let body = if arrow {
+            self.consume(TokenType::ARROW, "Expect '=>' after parameters.")?;
+            self.arrow_body()?
+        } else {
+            self.consume(TokenType::LEFT_BRACE, "Expect '{' before lambda body.")?;
+            self.block_statement()?
+        };
+
+        let name = Token::new(TokenType::IDENTIFIER, "lambda", None, start);
+        Ok(Expr::new(
+            ExprKind::Lambda(name, parameters, Box::new(body)),
+            self.span_from(start),
+        ))
+    }
 
-        Ok(Statement::FuncDecl(name, parameters, Box::new(body)))
+    fn arrow_body(&mut self) -> Result<Statement, LoxError> {
+        if self.is_match(TokenType::LEFT_BRACE) {
+            return self.block_statement();
+        }
+        let value = self.expression()?;
+        let span = value.span;
+        let body = Statement::new(StatementKind::Return(Some(value)), span);
+        Ok(Statement::new(
+            StatementKind::Block(VecDeque::from([body])),
+            span,
+        ))
+    }
+
+    // Whether the '(' at the current token starts the parameters of an
+    // arrow function rather than a grouping.
+    fn arrow_ahead(&self) -> bool {
+        let at = |i: usize| self.tokens.get(i).map(|t| &t.token_type);
+        let mut i = self.current + 1;
+        if at(i) == Some(&TokenType::IDENTIFIER) {
+            i += 1;
+            while at(i) == Some(&TokenType::COMMA) && at(i + 1) == Some(&TokenType::IDENTIFIER) {
+                i += 2;
+            }
+        }
+        at(i) == Some(&TokenType::RIGHT_PAREN) && at(i + 1) == Some(&TokenType::ARROW)
+    }
+
+    // `as` and `from` aren't keywords, so they stay usable as names.
+    fn import_declaration(&mut self) -> Result<Statement, LoxError> {
+        let start = self.previous().span;
+        let path = self.consume(TokenType::STRING, "Expect module path after 'import'.")?;
+        if !self.check_word("as") {
+            return Err(self.expected("Expect 'as' after module path."));
+        }
+        self.advance();
+        let name = self.consume(TokenType::IDENTIFIER, "Expect module name after 'as'.")?;
+        self.consume(TokenType::SEMICOLON, "Expect ';' after import.")?;
+        Ok(Statement::new(
+            StatementKind::Import(path, Imports::Module(name)),
+            self.span_from(start),
+        ))
+    }
+
+    fn names_import_declaration(&mut self) -> Result<Statement, LoxError> {
+        let start = self.previous().span;
+        let path = self.advance();
+        self.consume(TokenType::IMPORT, "Expect 'import' after module path.")?;
+        let mut names = Vec::new();
+        loop {
+            names.push(self.consume(TokenType::IDENTIFIER, "Expect name to import.")?);
+            if !self.is_match(TokenType::COMMA) {
+                break;
+            }
+        }
+        self.consume(TokenType::SEMICOLON, "Expect ';' after import.")?;
+        Ok(Statement::new(
+            StatementKind::Import(path, Imports::Names(names)),
+            self.span_from(start),
+        ))
     }
 
     fn var_declaration(&mut self) -> Result<Statement, LoxError> {
-        let name = self.consume(TokenType::IDENTIFIER, "Expect variable name.");
+        let start = self.previous().span;
+        let name = self.consume(TokenType::IDENTIFIER, "Expect variable name.")?;
 
-        let mut init = Expr::Literal(Object::Nil);
+        let mut init = Expr::new(ExprKind::Literal(Object::Nil), name.span);
         if self.is_match(TokenType::EQUAL) {
             init = self.expression()?;
         }
 
         self.consume(
             TokenType::SEMICOLON,
-            "Expecct ';' after variable declaration.",
-        );
+            "Expect ';' after variable declaration.",
+        )?;
 
-        Ok(Statement::VarDecl(name, init))
+        Ok(Statement::new(
+            StatementKind::VarDecl(name, init),
+            self.span_from(start),
+        ))
     }
 
     fn statement(&mut self) -> Result<Statement, LoxError> {
@@ -119,6 +252,18 @@ impl Parser {
         if self.is_match(TokenType::RETURN) {
             return self.return_statement();
         }
+        if self.is_match(TokenType::THROW) {
+            return self.throw_statement();
+        }
+        if self.is_match(TokenType::TRY) {
+            return self.try_statement();
+        }
+        if self.is_match(TokenType::BREAK) {
+            return self.loop_control(StatementKind::Break, "Expect ';' after 'break'.");
+        }
+        if self.is_match(TokenType::CONTINUE) {
+            return self.loop_control(StatementKind::Continue, "Expect ';' after 'continue'.");
+        }
         if self.is_match(TokenType::CLASS) {
             return self.class_statement();
         }
@@ -127,7 +272,8 @@ impl Parser {
     }
 
     fn for_statement(&mut self) -> Result<Statement, LoxError> {
-        self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'for'.");
+        let start = self.previous().span;
+        self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'for'.")?;
 
         let initializer = {
             if self.is_match(TokenType::SEMICOLON) {
@@ -146,7 +292,7 @@ impl Parser {
                 None
             }
         };
-        self.consume(TokenType::SEMICOLON, "Expect ';' after loop condition.");
+        self.consume(TokenType::SEMICOLON, "Expect ';' after loop condition.")?;
 
         let increment = {
             if !self.check(TokenType::RIGHT_PAREN) {
@@ -155,150 +301,324 @@ impl Parser {
                 None
             }
         };
-        self.consume(TokenType::RIGHT_PAREN, "Expect ')' after for clauses.");
-
-        let body = {
-            let stm = self.statement()?;
-            if let Some(expr) = increment {
-                let mut deque = VecDeque::with_capacity(2);
-                deque.push_back(Statement::Expr(expr));
-                deque.push_back(stm);
-                Statement::Block(deque)
-            } else {
-                stm
-            }
-        };
+        self.consume(TokenType::RIGHT_PAREN, "Expect ')' after for clauses.")?;
 
-        let while_stm = {
-            if let Some(cond) = condition {
-                Statement::While(cond, Box::new(body))
-            } else {
-                Statement::While(Expr::Literal(Object::Boolean(true)), Box::new(body))
-            }
-        };
+        let body = self.nested(Self::statement)?;
+
+        let span = self.span_from(start);
+        let cond =
+            condition.unwrap_or_else(|| Expr::new(ExprKind::Literal(Object::Boolean(true)), start));
+        let while_stm = Statement::new(
+            StatementKind::While(cond, Box::new(body), increment.map(Box::new)),
+            span,
+        );
 
         if let Some(init) = initializer {
             let mut deque = VecDeque::with_capacity(2);
             deque.push_back(init);
             deque.push_back(while_stm);
-            Ok(Statement::Block(deque))
+            Ok(Statement::new(StatementKind::Block(deque), span))
         } else {
             Ok(while_stm)
         }
     }
 
     fn class_statement(&mut self) -> Result<Statement, LoxError> {
-        let name = self.consume(TokenType::IDENTIFIER, "Expect class name.");
+        let start = self.previous().span;
+        let name = self.consume(TokenType::IDENTIFIER, "Expect class name.")?;
 
         let mut superclass = None;
         if self.is_match(TokenType::LESS) {
-            self.consume(TokenType::IDENTIFIER, "Expect superclass name.");
+            self.consume(TokenType::IDENTIFIER, "Expect superclass name.")?;
             superclass = Some(self.previous());
         }
 
-        self.consume(TokenType::LEFT_BRACE, "Expect '{' before class body.");
+        self.consume(TokenType::LEFT_BRACE, "Expect '{' before class body.")?;
 
         let mut methods: Vec<Statement> = Vec::new();
 
         while !self.check(TokenType::RIGHT_BRACE) && !self.is_at_end() {
-            methods.push(self.function("method".to_string())?);
+            let start = self.peek().span;
+            methods.push(self.function("method".to_string(), start)?);
         }
 
-        self.consume(TokenType::RIGHT_BRACE, "Expect '}' after class body.");
+        self.consume(TokenType::RIGHT_BRACE, "Expect '}' after class body.")?;
 
-        if let Some(superclass) = superclass {
-            Ok(Statement::ClassDecl(
-                name,
-                Some(Var::new(superclass)),
-                methods,
-            ))
-        } else {
-            Ok(Statement::ClassDecl(name, None, methods))
-        }
+        let superclass = superclass.map(Var::new);
+        Ok(Statement::new(
+            StatementKind::ClassDecl(name, superclass, methods),
+            self.span_from(start),
+        ))
     }
 
     fn while_statement(&mut self) -> Result<Statement, LoxError> {
-        self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'while'.");
+        let start = self.previous().span;
+        self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'while'.")?;
         let cond = self.expression()?;
-        self.consume(TokenType::RIGHT_PAREN, "Expect ')' after condition");
-        let body = self.statement()?;
+        self.consume(TokenType::RIGHT_PAREN, "Expect ')' after condition.")?;
+        let body = self.nested(Self::statement)?;
 
-        Ok(Statement::While(cond, Box::new(body)))
+        Ok(Statement::new(
+            StatementKind::While(cond, Box::new(body), None),
+            self.span_from(start),
+        ))
     }
 
     fn if_statement(&mut self) -> Result<Statement, LoxError> {
-        self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'if'.");
+        let start = self.previous().span;
+        self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'if'.")?;
         let condition = self.expression()?;
-        self.consume(TokenType::RIGHT_PAREN, "Expect ')' after if condition");
+        self.consume(TokenType::RIGHT_PAREN, "Expect ')' after if condition.")?;
 
-        let then_branch = self.statement()?;
+        let then_branch = self.nested(Self::statement)?;
 
         let mut else_branch = None;
         if self.is_match(TokenType::ELSE) {
-            else_branch = Some(Box::new(self.statement()?));
+            else_branch = Some(Box::new(self.nested(Self::statement)?));
         }
 
-        Ok(Statement::If(condition, Box::new(then_branch), else_branch))
+        Ok(Statement::new(
+            StatementKind::If(condition, Box::new(then_branch), else_branch),
+            self.span_from(start),
+        ))
     }
 
     fn block_statement(&mut self) -> Result<Statement, LoxError> {
+        let start = self.previous().span;
         let mut statements: VecDeque<Statement> = VecDeque::new();
 
+        self.depth += 1;
         while !self.check(TokenType::RIGHT_BRACE) && !self.is_at_end() {
-            statements.push_back(self.declaration()?);
+            if let Some(stmt) = self.declaration() {
+                statements.push_back(stmt);
+            }
         }
+        self.depth -= 1;
 
-        self.consume(TokenType::RIGHT_BRACE, "Expect '}' after block.");
-        Ok(Statement::Block(statements))
+        self.consume(TokenType::RIGHT_BRACE, "Expect '}' after block.")?;
+        Ok(Statement::new(
+            StatementKind::Block(statements),
+            self.span_from(start),
+        ))
     }
 
     fn print_statement(&mut self) -> Result<Statement, LoxError> {
+        let start = self.previous().span;
         let value = self.expression()?;
-        self.consume(TokenType::SEMICOLON, "Expect ';' after value.");
-        Ok(Statement::Print(value))
+        self.consume(TokenType::SEMICOLON, "Expect ';' after value.")?;
+        Ok(Statement::new(
+            StatementKind::Print(value),
+            self.span_from(start),
+        ))
     }
 
     fn return_statement(&mut self) -> Result<Statement, LoxError> {
-        let stm = {
+        let start = self.previous().span;
+        let value = {
             if !self.check(TokenType::SEMICOLON) {
-                Statement::Return(self.expression()?)
+                Some(self.expression()?)
             } else {
-                Statement::Return(Expr::Literal(Object::Nil))
+                None
             }
         };
 
-        self.consume(TokenType::SEMICOLON, "Expect ';' after return value");
-        Ok(stm)
+        self.consume(TokenType::SEMICOLON, "Expect ';' after return value.")?;
+        Ok(Statement::new(
+            StatementKind::Return(value),
+            self.span_from(start),
+        ))
+    }
+
+    fn throw_statement(&mut self) -> Result<Statement, LoxError> {
+        let start = self.previous().span;
+        let value = self.expression()?;
+        self.consume(TokenType::SEMICOLON, "Expect ';' after thrown value.")?;
+        Ok(Statement::new(
+            StatementKind::Throw(value),
+            self.span_from(start),
+        ))
+    }
+
+    fn try_statement(&mut self) -> Result<Statement, LoxError> {
+        let start = self.previous().span;
+        self.consume(TokenType::LEFT_BRACE, "Expect '{' after 'try'.")?;
+        let body = self.block_statement()?;
+
+        let catch = if self.is_match(TokenType::CATCH) {
+            self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'catch'.")?;
+            let name = self.consume(TokenType::IDENTIFIER, "Expect exception name.")?;
+            self.consume(TokenType::RIGHT_PAREN, "Expect ')' after exception name.")?;
+            self.consume(TokenType::LEFT_BRACE, "Expect '{' before catch body.")?;
+            Some((name, Box::new(self.block_statement()?)))
+        } else {
+            None
+        };
+
+        let finally = if self.is_match(TokenType::FINALLY) {
+            self.consume(TokenType::LEFT_BRACE, "Expect '{' after 'finally'.")?;
+            Some(Box::new(self.block_statement()?))
+        } else {
+            None
+        };
+
+        if catch.is_none() && finally.is_none() {
+            return Err(self.expected("Expect 'catch' or 'finally' after try block."));
+        }
+
+        Ok(Statement::new(
+            StatementKind::Try(Box::new(body), catch, finally),
+            self.span_from(start),
+        ))
+    }
+
+    fn loop_control(&mut self, kind: StatementKind, message: &str) -> Result<Statement, LoxError> {
+        let start = self.previous().span;
+        self.consume(TokenType::SEMICOLON, message)?;
+        Ok(Statement::new(kind, self.span_from(start)))
     }
 
     fn expression_statement(&mut self) -> Result<Statement, LoxError> {
         let expr = self.expression()?;
-        self.consume(TokenType::SEMICOLON, "Expect ';' after expression");
-        Ok(Statement::Expr(expr))
+        self.consume(TokenType::SEMICOLON, "Expect ';' after expression.")?;
+        let span = self.span_from(expr.span);
+        Ok(Statement::new(StatementKind::Expr(expr), span))
     }
 
     fn expression(&mut self) -> Result<Expr, LoxError> {
-        self.assignment()
+        self.nested(Self::assignment)
     }
 
     fn assignment(&mut self) -> Result<Expr, LoxError> {
-        let expr = self.or()?;
+        let expr = self.conditional()?;
 
         if self.is_match(TokenType::EQUAL) {
-            let _equals = self.previous();
-            let val = self.assignment()?;
-
-            if let Expr::Variable(name) = expr {
-                return Ok(Expr::Assignment(name, Box::new(val)));
-            } else if let Expr::Get(e, n) = expr {
-                return Ok(Expr::Set(Box::new(val), n, e));
+            let equals = self.previous();
+            let val = self.nested(Self::assignment)?;
+            let target = expr.span;
+            let span = expr.span.to(val.span);
+
+            match expr.kind {
+                ExprKind::Variable(name) => {
+                    return Ok(Expr::new(ExprKind::Assignment(name, Box::new(val)), span));
+                }
+                ExprKind::Get(e, n) => {
+                    return Ok(Expr::new(ExprKind::Set(Box::new(val), n, e), span));
+                }
+                ExprKind::Index(e, bracket, index) => {
+                    return Ok(Expr::new(
+                        ExprKind::SetIndex(e, bracket, index, Box::new(val)),
+                        span,
+                    ));
+                }
+                kind => {
+                    // Not worth synchronizing over, the parser isn't confused
+                    let err = Diagnostic::error(
+                        ErrorCode::InvalidAssignmentTarget,
+                        "Invalid assignment target.",
+                    )
+                    .with_span_label(target, "can't assign to this expression")
+                    .with_label(equals.span, "assignment happens here");
+                    self.error(err.into());
+                    return Ok(Expr::new(kind, target));
+                }
             }
+        }
+
+        if self.verify(&[
+            TokenType::PLUS_EQUAL,
+            TokenType::MINUS_EQUAL,
+            TokenType::STAR_EQUAL,
+            TokenType::SLASH_EQUAL,
+        ]) {
+            let operator = self.previous();
+            let val = self.nested(Self::assignment)?;
+            let span = expr.span.to(val.span);
+            return Ok(self.update(expr, operator, val, false, span));
+        }
+
+        Ok(expr)
+    }
+
+    // Builds the update of `target` by `operator`, reporting targets that
+    // can't be assigned to like `assignment` does.
+    fn update(
+        &mut self,
+        target: Expr,
+        operator: Token,
+        value: Expr,
+        postfix: bool,
+        span: Span,
+    ) -> Expr {
+        if !matches!(
+            target.kind,
+            ExprKind::Variable(_) | ExprKind::Get(..) | ExprKind::Index(..)
+        ) {
+            let err = Diagnostic::error(
+                ErrorCode::InvalidAssignmentTarget,
+                "Invalid assignment target.",
+            )
+            .with_span_label(target.span, "can't assign to this expression")
+            .with_label(operator.span, "assignment happens here");
+            self.error(err.into());
+            return target;
+        }
+
+        let token_type = match operator.token_type {
+            TokenType::PLUS_EQUAL | TokenType::PLUS_PLUS => TokenType::PLUS,
+            TokenType::MINUS_EQUAL | TokenType::MINUS_MINUS => TokenType::MINUS,
+            TokenType::STAR_EQUAL => TokenType::STAR,
+            TokenType::SLASH_EQUAL => TokenType::SLASH,
+            _ => unreachable!(),
+        };
+        let operator = Token {
+            token_type,
+            ..operator
+        };
+        Expr::new(
+            ExprKind::Update(Box::new(target), operator, Box::new(value), postfix),
+            span,
+        )
+    }
 
-            return Err(LoxError::ParsingError(
-                "Invalid assignment target.".to_string(),
+    // Right-associative, so `a ? b : c ? d : e` is `a ? b : (c ? d : e)`.
+    // The then branch is a full expression, as between parentheses.
+    fn conditional(&mut self) -> Result<Expr, LoxError> {
+        let condition = self.coalesce()?;
+
+        if self.is_match(TokenType::QUESTION) {
+            let then_branch = self.expression()?;
+            self.consume(
+                TokenType::COLON,
+                "Expect ':' after then branch of conditional expression.",
+            )?;
+            let else_branch = self.nested(Self::conditional)?;
+            let span = condition.span.to(else_branch.span);
+            return Ok(Expr::new(
+                ExprKind::Conditional(
+                    Box::new(condition),
+                    Box::new(then_branch),
+                    Box::new(else_branch),
+                ),
+                span,
             ));
         }
 
+        Ok(condition)
+    }
+
+    fn coalesce(&mut self) -> Result<Expr, LoxError> {
+        let mut expr = self.or()?;
+
+        while self.is_match(TokenType::QUESTION_QUESTION) {
+            let operator = self.previous();
+            let right = self.or()?;
+            let span = expr.span.to(right.span);
+            expr = Expr::new(
+                ExprKind::Logical(Box::new(expr), operator, Box::new(right)),
+                span,
+            );
+        }
+
         Ok(expr)
     }
 
@@ -308,7 +628,11 @@ impl Parser {
         while self.is_match(TokenType::OR) {
             let operator = self.previous();
             let right = self.and()?;
-            expr = Expr::Logical(Box::new(expr), operator, Box::new(right));
+            let span = expr.span.to(right.span);
+            expr = Expr::new(
+                ExprKind::Logical(Box::new(expr), operator, Box::new(right)),
+                span,
+            );
         }
 
         Ok(expr)
@@ -320,7 +644,11 @@ impl Parser {
         while self.is_match(TokenType::AND) {
             let operator = self.previous();
             let right = self.equality()?;
-            expr = Expr::Logical(Box::new(expr), operator, Box::new(right));
+            let span = expr.span.to(right.span);
+            expr = Expr::new(
+                ExprKind::Logical(Box::new(expr), operator, Box::new(right)),
+                span,
+            );
         }
 
         Ok(expr)
@@ -329,10 +657,11 @@ impl Parser {
     fn equality(&mut self) -> Result<Expr, LoxError> {
         let mut expr = self.comparison()?;
 
-        while self.verify(vec![TokenType::BANG_EQUAL, TokenType::EQUAL_EQUAL]) {
+        while self.verify(&[TokenType::BANG_EQUAL, TokenType::EQUAL_EQUAL]) {
             let op = self.previous();
             let right = self.comparison()?;
-            expr = Expr::Binary(Box::new(expr), op, Box::new(right));
+            let span = expr.span.to(right.span);
+            expr = Expr::new(ExprKind::Binary(Box::new(expr), op, Box::new(right)), span);
         }
 
         Ok(expr)
@@ -341,7 +670,7 @@ impl Parser {
     fn comparison(&mut self) -> Result<Expr, LoxError> {
         let mut expr = self.term()?;
 
-        while self.verify(vec![
+        while self.verify(&[
             TokenType::GREATER,
             TokenType::GREATER_EQUAL,
             TokenType::LESS,
@@ -349,7 +678,8 @@ impl Parser {
         ]) {
             let op = self.previous();
             let right = self.term()?;
-            expr = Expr::Binary(Box::new(expr), op, Box::new(right));
+            let span = expr.span.to(right.span);
+            expr = Expr::new(ExprKind::Binary(Box::new(expr), op, Box::new(right)), span);
         }
 
         Ok(expr)
@@ -358,10 +688,11 @@ impl Parser {
     fn term(&mut self) -> Result<Expr, LoxError> {
         let mut expr = self.factor()?;
 
-        while self.verify(vec![TokenType::MINUS, TokenType::PLUS]) {
+        while self.verify(&[TokenType::MINUS, TokenType::PLUS]) {
             let op = self.previous();
             let right = self.factor()?;
-            expr = Expr::Binary(Box::new(expr), op, Box::new(right));
+            let span = expr.span.to(right.span);
+            expr = Expr::new(ExprKind::Binary(Box::new(expr), op, Box::new(right)), span);
         }
         Ok(expr)
     }
@@ -369,52 +700,138 @@ impl Parser {
     fn factor(&mut self) -> Result<Expr, LoxError> {
         let mut expr = self.unary()?;
 
-        while self.verify(vec![TokenType::SLASH, TokenType::STAR]) {
+        while self.verify(&[
+            TokenType::SLASH,
+            TokenType::STAR,
+            TokenType::PERCENT,
+            TokenType::TILDE_SLASH,
+        ]) {
             let op = self.previous();
             let right = self.unary()?;
-            expr = Expr::Binary(Box::new(expr), op, Box::new(right));
+            let span = expr.span.to(right.span);
+            expr = Expr::new(ExprKind::Binary(Box::new(expr), op, Box::new(right)), span);
         }
 
         Ok(expr)
     }
 
     fn unary(&mut self) -> Result<Expr, LoxError> {
-        if self.verify(vec![TokenType::BANG, TokenType::MINUS]) {
+        if self.verify(&[TokenType::BANG, TokenType::MINUS]) {
             let op = self.previous();
-            let right = self.unary()?;
-            return Ok(Expr::Unary(op, Box::new(right)));
+            let right = self.nested(Self::unary)?;
+            let span = op.span.to(right.span);
+            return Ok(Expr::new(ExprKind::Unary(op, Box::new(right)), span));
+        }
+
+        if self.verify(&[TokenType::PLUS_PLUS, TokenType::MINUS_MINUS]) {
+            let op = self.previous();
+            let target = self.nested(Self::unary)?;
+            let span = op.span.to(target.span);
+            let one = Expr::new(ExprKind::Literal(Object::Number(1.0)), op.span);
+            return Ok(self.update(target, op, one, false, span));
+        }
+
+        self.power()
+    }
+
+    // `**` binds tighter than a unary operator on its left, so `-2 ** 2`
+    // is -4, and is right-associative: `2 ** 3 ** 2` is `2 ** 9`.
+    fn power(&mut self) -> Result<Expr, LoxError> {
+        let base = self.postfix()?;
+
+        if self.is_match(TokenType::STAR_STAR) {
+            let op = self.previous();
+            let exponent = self.nested(Self::unary)?;
+            let span = base.span.to(exponent.span);
+            return Ok(Expr::new(
+                ExprKind::Binary(Box::new(base), op, Box::new(exponent)),
+                span,
+            ));
+        }
+
+        Ok(base)
+    }
+
+    fn postfix(&mut self) -> Result<Expr, LoxError> {
+        let expr = self.call()?;
+
+        if self.verify(&[TokenType::PLUS_PLUS, TokenType::MINUS_MINUS]) {
+            let op = self.previous();
+            let span = expr.span.to(op.span);
+            let one = Expr::new(ExprKind::Literal(Object::Number(1.0)), op.span);
+            return Ok(self.update(expr, op, one, true, span));
         }
 
-        self.call()
+        Ok(expr)
     }
 
     fn call(&mut self) -> Result<Expr, LoxError> {
         let mut expr = self.primary()?;
+        let mut optional = false;
 
         loop {
             if self.is_match(TokenType::LEFT_PAREN) {
                 expr = self.finish_call(expr)?;
             } else if self.is_match(TokenType::DOT) {
-                let name = self.consume(TokenType::IDENTIFIER, "Expect property name after '.'.");
-                expr = Expr::Get(Box::new(expr), name);
+                let name =
+                    self.consume(TokenType::IDENTIFIER, "Expect property name after '.'.")?;
+                let span = expr.span.to(name.span);
+                expr = Expr::new(ExprKind::Get(Box::new(expr), name), span);
+            } else if self.is_match(TokenType::LEFT_BRACKET) {
+                expr = self.finish_index(expr)?;
+            } else if self.is_match(TokenType::QUESTION_DOT) {
+                optional = true;
+                let span = expr.span;
+                let receiver = Expr::new(ExprKind::Optional(Box::new(expr)), span);
+                if self.is_match(TokenType::LEFT_PAREN) {
+                    expr = self.finish_call(receiver)?;
+                } else if self.is_match(TokenType::LEFT_BRACKET) {
+                    expr = self.finish_index(receiver)?;
+                } else {
+                    let name = self.consume(
+                        TokenType::IDENTIFIER,
+                        "Expect property name, '(' or '[' after '?.'.",
+                    )?;
+                    let span = receiver.span.to(name.span);
+                    expr = Expr::new(ExprKind::Get(Box::new(receiver), name), span);
+                }
             } else {
                 break;
             }
         }
 
+        // The whole chain is skipped when a `?.` receiver is nil, so
+        // `a?.b.c` doesn't fail on `.c`
+        if optional {
+            let span = expr.span;
+            expr = Expr::new(ExprKind::Chain(Box::new(expr)), span);
+        }
+
         Ok(expr)
     }
 
+    fn finish_index(&mut self, object: Expr) -> Result<Expr, LoxError> {
+        let bracket = self.previous();
+        let index = self.expression()?;
+        self.consume(TokenType::RIGHT_BRACKET, "Expect ']' after index.")?;
+        let span = self.span_from(object.span);
+        Ok(Expr::new(
+            ExprKind::Index(Box::new(object), bracket, Box::new(index)),
+            span,
+        ))
+    }
+
     fn finish_call(&mut self, callee: Expr) -> Result<Expr, LoxError> {
         let mut arguments = Vec::new();
         if !self.check(TokenType::RIGHT_PAREN) {
             loop {
-                if arguments.len() >= 255 {
-                    return Err(LoxError::Error(
-                        "Can't have more than 
-                    255 arguments."
-                            .to_string(),
-                    ));
+                if arguments.len() == 255 {
+                    let err = Diagnostic::error(
+                        ErrorCode::TooManyArguments,
+                        "Can't have more than 255 arguments.",
+                    )
+                    .with_span(self.peek().span);
+                    self.error(err.into());
                 }
                 arguments.push(self.expression()?);
                 if !self.is_match(TokenType::COMMA) {
@@ -422,128 +839,375 @@ impl Parser {
                 }
             }
         }
-        self.consume(TokenType::RIGHT_PAREN, "Expect ')' after arguments");
+        let paren = self.consume(TokenType::RIGHT_PAREN, "Expect ')' after arguments.")?;
 
-        Ok(Expr::Call(Box::new(callee), arguments))
+        let span = callee.span.to(paren.span);
+        Ok(Expr::new(ExprKind::Call(Box::new(callee), arguments), span))
     }
 
     fn primary(&mut self) -> Result<Expr, LoxError> {
         if self.is_match(TokenType::FALSE) {
-            return Ok(Expr::Literal(Object::Boolean(false)));
+            return Ok(self.literal(Object::Boolean(false)));
         };
         if self.is_match(TokenType::TRUE) {
-            return Ok(Expr::Literal(Object::Boolean(true)));
+            return Ok(self.literal(Object::Boolean(true)));
         };
         if self.is_match(TokenType::NIL) {
-            return Ok(Expr::Literal(Object::Nil));
+            return Ok(self.literal(Object::Nil));
         };
 
-        if self.verify(vec![TokenType::NUMBER, TokenType::STRING]) {
-            return Ok(Expr::Literal(self.previous().literal.unwrap()));
+        if self.verify(&[TokenType::NUMBER, TokenType::STRING]) {
+            let literal = self.previous().literal.unwrap();
+            return Ok(self.literal(literal));
+        }
+
+        if self.is_match(TokenType::FUN) {
+            let start = self.previous().span;
+            self.consume(TokenType::LEFT_PAREN, "Expect '(' after 'fun'.")?;
+            return self.lambda(start, false);
+        }
+
+        if self.check(TokenType::LEFT_PAREN) && self.arrow_ahead() {
+            let start = self.advance().span;
+            return self.lambda(start, true);
         }
 
         if self.is_match(TokenType::LEFT_PAREN) {
+            let start = self.previous().span;
             let expr = self.expression()?;
-            self.consume(TokenType::RIGHT_PAREN, "Expect ')' after expression.");
-            return Ok(Expr::Grouping(Box::new(expr)));
+            self.consume(TokenType::RIGHT_PAREN, "Expect ')' after expression.")?;
+            return Ok(Expr::new(
+                ExprKind::Grouping(Box::new(expr)),
+                self.span_from(start),
+            ));
+        }
+
+        if self.is_match(TokenType::LEFT_BRACKET) {
+            let start = self.previous().span;
+            let mut elements = Vec::new();
+            while !self.check(TokenType::RIGHT_BRACKET) {
+                elements.push(self.expression()?);
+                if !self.is_match(TokenType::COMMA) {
+                    break;
+                }
+            }
+            self.consume(TokenType::RIGHT_BRACKET, "Expect ']' after list elements.")?;
+            return Ok(Expr::new(ExprKind::List(elements), self.span_from(start)));
+        }
+
+        // A '{' starting a statement is a block, so this is always a map
+        if self.is_match(TokenType::LEFT_BRACE) {
+            let start = self.previous().span;
+            let mut entries = Vec::new();
+            while !self.check(TokenType::RIGHT_BRACE) {
+                let key = self.expression()?;
+                self.consume(TokenType::COLON, "Expect ':' after map key.")?;
+                entries.push((key, self.expression()?));
+                if !self.is_match(TokenType::COMMA) {
+                    break;
+                }
+            }
+            self.consume(TokenType::RIGHT_BRACE, "Expect '}' after map entries.")?;
+            return Ok(Expr::new(ExprKind::Map(entries), self.span_from(start)));
         }
 
         if self.is_match(TokenType::IDENTIFIER) {
-            return Ok(Expr::Variable(Var::new(self.previous())));
+            let name = self.previous();
+            let span = name.span;
+            return Ok(Expr::new(ExprKind::Variable(Var::new(name)), span));
         }
 
         if self.is_match(TokenType::THIS) {
-            return Ok(Expr::This(Var::new(self.previous())));
+            let keyword = self.previous();
+            let span = keyword.span;
+            return Ok(Expr::new(ExprKind::This(Var::new(keyword)), span));
         }
 
         if self.is_match(TokenType::SUPER) {
             let keyword = self.previous();
-            self.consume(TokenType::DOT, "Expect '.' after 'super'.");
-            let method = self.consume(TokenType::IDENTIFIER, "Expect superclass method name.");
-            return Ok(Expr::Super(Var::new(keyword), method));
+            self.consume(TokenType::DOT, "Expect '.' after 'super'.")?;
+            let method = self.consume(TokenType::IDENTIFIER, "Expect superclass method name.")?;
+            let span = keyword.span.to(method.span);
+            return Ok(Expr::new(ExprKind::Super(Var::new(keyword), method), span));
         }
 
-        Err(LoxError::NotExpression)
+        let token = self.peek();
+        Err(
+            Diagnostic::error(ErrorCode::ExpectedExpression, "Expect expression.")
+                .with_span_label(token.span, format!("found '{}'", Self::describe(&token)))
+                .into(),
+        )
     }
 
-    fn synchronize(&mut self) -> Result<(), LoxError> {
-        if self.advance().is_none() {
-            return Err(LoxError::TokenListEmpty);
+    // Runs `parse` one level of nesting deeper. Past `MAX_NESTING` the rest
+    // of the input is skipped, as every later error would follow from this.
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, LoxError>,
+    ) -> Result<T, LoxError> {
+        if self.nesting == MAX_NESTING {
+            let token = self.peek();
+            self.current = self.tokens.len() - 1;
+            return Err(
+                Diagnostic::error(ErrorCode::TooMuchNesting, "Too much nesting.")
+                    .with_span_label(token.span, "nested too deeply")
+                    .with_note(format!(
+                        "statements and expressions can be nested at most {} levels deep",
+                        MAX_NESTING
+                    ))
+                    .into(),
+            );
         }
 
-        while !self.is_at_end() {
+        self.nesting += 1;
+        let result = parse(self);
+        self.nesting -= 1;
+        result
+    }
+
+    fn error(&mut self, err: LoxError) {
+        let gave_up = self.errors.last().map(|d| d.code) == Some(ErrorCode::TooMuchNesting);
+        if gave_up {
+            return;
+        }
+        if let LoxError::Diagnostic(d) = err {
+            self.errors.push(*d);
+        }
+    }
+
+    // Discards tokens until the start of the next statement. Inside a block
+    // the closing brace is left alone so the block can still end cleanly.
+    fn synchronize(&mut self) {
+        let closes_block = |p: &Self| p.depth > 0 && p.check(TokenType::RIGHT_BRACE);
+
+        if !closes_block(self) {
+            self.advance();
+        }
+
+        while !self.is_at_end() && !closes_block(self) {
             if self.previous().token_type == TokenType::SEMICOLON {
-                return Err(LoxError::Error("Sync".to_string()));
-            };
-
-            match self.peek().unwrap().token_type {
-                TokenType::CLASS => (),
-                TokenType::FUN => (),
-                TokenType::VAR => (),
-                TokenType::FOR => (),
-                TokenType::IF => (),
-                TokenType::WHILE => (),
-                TokenType::PRINT => (),
-                TokenType::RETURN => (),
-                _ => unreachable!(),
+                return;
+            }
+
+            match self.peek().token_type {
+                TokenType::CLASS
+                | TokenType::FUN
+                | TokenType::VAR
+                | TokenType::FOR
+                | TokenType::IF
+                | TokenType::WHILE
+                | TokenType::PRINT
+                | TokenType::RETURN
+                | TokenType::BREAK
+                | TokenType::CONTINUE
+                | TokenType::THROW
+                | TokenType::TRY
+                | TokenType::IMPORT => return,
+                _ => (),
             }
 
             self.advance();
         }
-        Err(LoxError::Error("Sync".to_string()))
     }
 
     fn is_at_end(&self) -> bool {
-        self.current >= self.tokens.len()
+        self.peek().token_type == TokenType::EOF
     }
 
-    fn consume(&mut self, token: TokenType, msg: &str) -> Token {
-        if self.is_match(token) {
-            self.previous()
+    fn consume(&mut self, token: TokenType, msg: &str) -> Result<Token, LoxError> {
+        if self.is_match(token.clone()) {
+            return Ok(self.previous());
+        }
+        if token != TokenType::SEMICOLON || self.current == 0 {
+            return Err(self.expected(msg));
+        }
+
+        // Point right behind the token that should have been terminated
+        let found = self.peek();
+        let previous = self.previous();
+        let column = previous.span.column + previous.lexeme.chars().count();
+        let end = Span::new(
+            previous.span.end,
+            previous.span.end,
+            previous.span.line,
+            column,
+        );
+        Err(Diagnostic::error(ErrorCode::ExpectedToken, msg)
+            .with_span_label(end, "expected ';'")
+            .with_label(found.span, format!("found '{}'", Self::describe(&found)))
+            .with_suggestion(
+                previous.span,
+                "add a semicolon",
+                format!("{};", previous.lexeme),
+            )
+            .into())
+    }
+
+    // An error for a missing token, pointing at the one found instead.
+    fn expected(&self, msg: &str) -> LoxError {
+        let found = self.peek();
+        Diagnostic::error(ErrorCode::ExpectedToken, msg)
+            .with_span_label(found.span, format!("found '{}'", Self::describe(&found)))
+            .into()
+    }
+
+    fn describe(token: &Token) -> &str {
+        if token.token_type == TokenType::EOF {
+            "end of file"
         } else {
-            panic!("{}", msg);
+            &token.lexeme
         }
     }
 
     fn is_match(&mut self, token: TokenType) -> bool {
-        if let Some(tok) = self.tokens.get(self.current) {
-            if tok.token_type == token {
-                self.advance();
-                return true;
-            }
+        if self.check(token) {
+            self.advance();
+            return true;
         }
         false
     }
 
-    fn verify(&mut self, tokens: Vec<TokenType>) -> bool {
-        if let Some(tok) = self.tokens.get(self.current) {
-            if tokens.iter().any(|x| *x == tok.token_type) {
-                self.advance();
-                return true;
-            }
+    fn verify(&mut self, tokens: &[TokenType]) -> bool {
+        if tokens.contains(&self.peek().token_type) {
+            self.advance();
+            return true;
         }
 
         false
     }
 
-    fn advance(&mut self) -> Option<Token> {
-        if let Some(tok) = self.tokens.get(self.current) {
+    // Never moves past the trailing EOF token.
+    fn advance(&mut self) -> Token {
+        if !self.is_at_end() {
             self.current += 1;
-            Some(tok.clone())
-        } else {
-            None
         }
+        self.previous()
     }
 
     fn check(&self, t: TokenType) -> bool {
-        self.tokens.get(self.current).unwrap().token_type == t
+        !self.is_at_end() && self.peek().token_type == t
+    }
+
+    fn check_word(&self, word: &str) -> bool {
+        let token = self.peek();
+        token.token_type == TokenType::IDENTIFIER && token.lexeme == word
+    }
+
+    fn check_next(&self, t: TokenType) -> bool {
+        self.tokens.get(self.current + 1).map(|t| &t.token_type) == Some(&t)
+    }
+
+    fn peek(&self) -> Token {
+        self.tokens[self.current].clone()
+    }
+
+    fn previous(&self) -> Token {
+        self.tokens[self.current.saturating_sub(1)].clone()
     }
 
-    fn peek(&self) -> Option<Token> {
-        self.tokens.get(self.current).map(|x| x.to_owned())
+    fn literal(&self, object: Object) -> Expr {
+        Expr::new(ExprKind::Literal(object), self.previous().span)
     }
 
-    fn previous(&mut self) -> Token {
-        return self.tokens.get(self.current - 1).unwrap().clone();
+    fn span_from(&self, start: Span) -> Span {
+        start.to(self.previous().span)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::Scanner;
+
+    fn parse(source: &str) -> Result<Vec<Statement>, Vec<Diagnostic>> {
+        let mut scanner = Scanner::new(source.to_string());
+        let _ = scanner.scan_tokens();
+        Parser::new(scanner.get_tokens()).parse()
+    }
+
+    #[test]
+    fn reports_every_error() {
+        let errors = parse("var a = ;\nprint 1\nvar b = 2;\n{ print ; }\nprint (1;").unwrap_err();
+        let lines: Vec<usize> = errors.iter().map(|e| e.span().unwrap().line).collect();
+        assert_eq!(lines, vec![1, 2, 4, 5]);
+    }
+
+    #[test]
+    fn recovers_inside_blocks() {
+        let errors = parse("{ print a b }\nprint 1;").unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, ErrorCode::ExpectedToken);
+    }
+
+    #[test]
+    fn empty_token_stream() {
+        assert_eq!(Parser::new(Vec::new()).parse().unwrap(), Vec::new());
+    }
+
+    #[test]
+    fn deep_nesting_is_an_error() {
+        let deep = [
+            format!("print {}1{};", "(".repeat(3000), ")".repeat(3000)),
+            format!("print {}1;", "-".repeat(20_000)),
+            format!("print {}1;", "- ".repeat(20_000)),
+            format!("print 2{};", " ** 2".repeat(5000)),
+            format!("var a; {}1;", "a = ".repeat(5000)),
+            format!("print {}1;", "true ? 1 : ".repeat(5000)),
+            format!("{}{}", "{".repeat(3000), "}".repeat(3000)),
+            format!("{}print 1;", "if (true) ".repeat(3000)),
+        ];
+        for source in &deep {
+            let errors = parse(source).unwrap_err();
+            assert_eq!(errors.len(), 1, "{}", &source[..20]);
+            assert_eq!(errors[0].code, ErrorCode::TooMuchNesting);
+        }
+
+        // The most deeply nested code accepted still runs, on a test
+        // thread's stack, with the forms that take the most of it
+        let n = MAX_NESTING - 2;
+        let deepest = [
+            format!("print {}1{};", "(".repeat(n), ")".repeat(n)),
+            format!("print {}1{};", "[".repeat(n), "]".repeat(n)),
+            format!(
+                "fun f(x) {{ return x; }} print {}1{};",
+                "f(".repeat(n),
+                ")".repeat(n)
+            ),
+            format!("var f = {}1; print f();", "() => ".repeat(n)),
+        ];
+        for source in &deepest {
+            assert!(parse(source).is_ok(), "{}", source);
+            let value = crate::Interpreter::builder()
+                .stdout(std::io::sink())
+                .build()
+                .eval(source);
+            assert!(value.is_ok(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn never_panics() {
+        let pieces = [
+            "var", "fun", "class", "if", "else", "while", "for", "return", "break", "continue",
+            "throw", "try", "catch", "finally", "import", "from", "as", "print", "this", "super",
+            "x", "1", "\"s\"", "(", ")", "{", "}", "[", "]", ",", ":", ".", ";", "=", "==", "+",
+            "-", "!", "<", "=>", "%", "**", "~/", "+=", "-=", "*=", "/=", "++", "--", "?", "??",
+            "?.", "and", "or", "nil",
+        ];
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+
+        for _ in 0..2000 {
+            let len = next() % 24;
+            let source: Vec<&str> = (0..len)
+                .map(|_| pieces[(next() % pieces.len() as u64) as usize])
+                .collect();
+            let _ = parse(&source.join(" "));
+        }
     }
 }
@@ -1,5 +1,7 @@
-use crate::expressions::{Expr, Var};
-use crate::statements::Statement;
+use crate::diagnostic::{Diagnostic, ErrorCode};
+use crate::expressions::{Expr, ExprKind, Var};
+use crate::span::Span;
+use crate::statements::{Imports, Statement, StatementKind};
 use crate::tokens::Token;
 
 use std::collections::HashMap;
@@ -7,6 +9,7 @@ use std::collections::HashMap;
 #[derive(Debug, Clone, PartialEq)]
 enum FunctionType {
     None,
+    Function,
     Method,
     Initializer,
 }
@@ -15,72 +18,160 @@ enum FunctionType {
 pub enum ClassType {
     None,
     Class,
+    Subclass,
 }
 
+#[derive(Debug)]
+struct Binding {
+    defined: bool,
+    span: Span,
+}
+
+/// Resolves every local variable to the number of scopes between its use
+/// and its declaration. Top-level code isn't tracked: names that can't be
+/// found in a local scope are looked up in the global environment at
+/// runtime.
 #[derive(Debug)]
 pub struct Resolver {
-    scopes: Vec<HashMap<String, bool>>,
+    scopes: Vec<HashMap<String, Binding>>,
     current_class: ClassType,
     current_function: FunctionType,
+    // Loops around the current statement, within the current function
+    loop_depth: usize,
+    errors: Vec<Diagnostic>,
 }
 
 impl Resolver {
-    pub fn run(stms: &mut [Statement]) {
+    pub fn run(stms: &mut [Statement]) -> Result<(), Vec<Diagnostic>> {
         let mut resolver = Resolver {
-            scopes: vec![HashMap::new()],
+            scopes: Vec::new(),
             current_class: ClassType::None,
             current_function: FunctionType::None,
+            loop_depth: 0,
+            errors: Vec::new(),
         };
         for stm in stms {
             resolver.resolve_stmt(stm);
         }
+
+        if resolver.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(resolver.errors)
+        }
     }
 }
 
 impl Resolver {
     fn resolve_stmt(&mut self, stmt: &mut Statement) {
-        match stmt {
-            Statement::Block(stms) => {
+        match &mut stmt.kind {
+            StatementKind::Block(stms) => {
                 self.begin_scope();
                 for stm in stms {
                     self.resolve_stmt(stm);
                 }
                 self.end_scope();
             }
-            Statement::VarDecl(token, exp) => {
+            StatementKind::VarDecl(token, exp) => {
                 self.declare(token);
                 self.resolve_exp(exp);
                 self.define(token);
             }
-            Statement::FuncDecl(name, args, body) => {
+            StatementKind::FuncDecl(name, args, body) => {
                 self.declare(name);
                 self.define(name);
-                self.resolve_function(name, args, body);
+                self.resolve_function(args, body, FunctionType::Function);
             }
-            Statement::Expr(e) => {
+            StatementKind::Expr(e) => {
                 self.resolve_exp(e);
             }
-            Statement::If(e, stmt1, stmt2) => {
+            StatementKind::If(e, stmt1, stmt2) => {
                 self.resolve_exp(e);
                 self.resolve_stmt(stmt1);
                 if let Some(stm) = stmt2 {
                     self.resolve_stmt(stm);
                 }
             }
-            Statement::Print(e) => {
+            StatementKind::Print(e) => {
                 self.resolve_exp(e);
             }
-            Statement::Return(e) => {
-                if self.current_function == FunctionType::Initializer {
-                    panic!("Cant't return a value from an initializer.");
+            StatementKind::Return(e) => {
+                if self.current_function == FunctionType::None {
+                    self.error(
+                        Diagnostic::error(
+                            ErrorCode::TopLevelReturn,
+                            "Can't return from top-level code.",
+                        )
+                        .with_span(stmt.span),
+                    );
                 }
+                if let Some(e) = e {
+                    if self.current_function == FunctionType::Initializer {
+                        self.error(
+                            Diagnostic::error(
+                                ErrorCode::ReturnFromInitializer,
+                                "Can't return a value from an initializer.",
+                            )
+                            .with_span(e.span)
+                            .with_note("an initializer always returns 'this'"),
+                        );
+                    }
+                    self.resolve_exp(e);
+                }
+            }
+            StatementKind::While(e, body, increment) => {
                 self.resolve_exp(e);
+                self.loop_depth += 1;
+                self.resolve_stmt(body);
+                self.loop_depth -= 1;
+                if let Some(increment) = increment {
+                    self.resolve_exp(increment);
+                }
             }
-            Statement::While(e, body) => {
+            StatementKind::Import(_path, imports) => match imports {
+                Imports::Module(name) => {
+                    self.declare(name);
+                    self.define(name);
+                }
+                Imports::Names(names) => {
+                    for name in names.iter() {
+                        self.declare(name);
+                        self.define(name);
+                    }
+                }
+            },
+            StatementKind::Throw(e) => {
                 self.resolve_exp(e);
+            }
+            StatementKind::Try(body, catch, finally) => {
                 self.resolve_stmt(body);
+                if let Some((name, body)) = catch {
+                    self.begin_scope();
+                    self.declare(name);
+                    self.define(name);
+                    self.resolve_stmt(body);
+                    self.end_scope();
+                }
+                if let Some(finally) = finally {
+                    self.resolve_stmt(finally);
+                }
+            }
+            StatementKind::Break | StatementKind::Continue => {
+                if self.loop_depth == 0 {
+                    let keyword = match stmt.kind {
+                        StatementKind::Break => "break",
+                        _ => "continue",
+                    };
+                    self.error(
+                        Diagnostic::error(
+                            ErrorCode::OutsideLoop,
+                            format!("Can't use '{}' outside of a loop.", keyword),
+                        )
+                        .with_span(stmt.span),
+                    );
+                }
             }
-            Statement::ClassDecl(name, superclass, methods) => {
+            StatementKind::ClassDecl(name, superclass, methods) => {
                 let enclosing_class = self.current_class.clone();
                 self.current_class = ClassType::Class;
 
@@ -89,34 +180,33 @@ impl Resolver {
 
                 if let Some(superclass) = superclass {
                     if superclass.name() == name.lexeme {
-                        panic!("A class can't inherit from itself");
+                        self.error(
+                            Diagnostic::error(
+                                ErrorCode::SelfInheritance,
+                                "A class can't inherit from itself.",
+                            )
+                            .with_span(superclass.identifier.span)
+                            .with_label(name.span, "class declared here"),
+                        );
                     }
+                    self.current_class = ClassType::Subclass;
                     self.resolve_var(superclass);
 
                     self.begin_scope();
-                    self.scopes
-                        .last_mut()
-                        .unwrap()
-                        .insert(superclass.name().to_string(), true);
+                    self.insert("super", superclass.identifier.span);
                 }
 
                 self.begin_scope();
-                self.scopes
-                    .last_mut()
-                    .unwrap()
-                    .insert("this".to_string(), true);
+                self.insert("this", name.span);
 
                 for method in methods {
-                    let declaration = self.current_function.clone();
-                    self.current_function = FunctionType::Method;
-                    if let Statement::FuncDecl(name, args, body) = method {
-                        if name.to_string() == "init" {
-                            self.current_function = FunctionType::Initializer;
-                        }
-                        self.resolve_function(name, args, body);
-                        self.current_function = declaration;
-                    } else {
-                        panic!("Resolver error ClassDecl");
+                    if let StatementKind::FuncDecl(name, args, body) = &mut method.kind {
+                        let kind = if name.lexeme == "init" {
+                            FunctionType::Initializer
+                        } else {
+                            FunctionType::Method
+                        };
+                        self.resolve_function(args, body, kind);
                     }
                 }
 
@@ -131,57 +221,123 @@ impl Resolver {
     }
 
     fn resolve_exp(&mut self, exp: &mut Expr) {
-        match exp {
-            Expr::Binary(e1, _t, e2) => {
+        match &mut exp.kind {
+            ExprKind::Binary(e1, _t, e2) => {
                 self.resolve_exp(e1);
                 self.resolve_exp(e2);
             }
-            Expr::Call(e, vec_e) => {
+            ExprKind::Call(e, vec_e) => {
                 self.resolve_exp(e);
                 for e1 in vec_e {
                     self.resolve_exp(e1);
                 }
             }
-            Expr::Grouping(e) => {
+            ExprKind::Grouping(e) => {
                 self.resolve_exp(e);
             }
-            Expr::Literal(_o) => {}
-            Expr::Logical(e1, _t, e2) => {
+            ExprKind::Literal(_o) => {}
+            ExprKind::Logical(e1, _t, e2) => {
                 self.resolve_exp(e1);
                 self.resolve_exp(e2);
             }
-            Expr::Unary(_t, e) => {
+            ExprKind::Conditional(condition, then_branch, else_branch) => {
+                self.resolve_exp(condition);
+                self.resolve_exp(then_branch);
+                self.resolve_exp(else_branch);
+            }
+            ExprKind::Unary(_t, e) => {
                 self.resolve_exp(e);
             }
-            Expr::Assignment(var, e) => {
+            ExprKind::Assignment(var, e) => {
                 self.resolve_exp(e);
                 self.resolve_var(var)
             }
-            Expr::Variable(var) => {
-                if let Some(b) = self.scopes.last().unwrap().get(var.name()) {
-                    if !(*b) {
-                        panic!(
-                            "Cant't read local variable in its own initializer. {}",
-                            var.name()
-                        );
-                    }
+            ExprKind::Variable(var) => {
+                let in_initializer = self
+                    .scopes
+                    .last()
+                    .and_then(|scope| scope.get(var.name()))
+                    .filter(|binding| !binding.defined)
+                    .map(|binding| binding.span);
+                if let Some(declaration) = in_initializer {
+                    self.error(
+                        Diagnostic::error(
+                            ErrorCode::LocalInOwnInitializer,
+                            "Can't read local variable in its own initializer.",
+                        )
+                        .with_span(var.identifier.span)
+                        .with_label(declaration, "variable declared here"),
+                    );
                 }
                 self.resolve_var(var);
             }
-            Expr::Get(e, _name) => {
+            ExprKind::Get(e, _name) => {
                 self.resolve_exp(e);
             }
-            Expr::Set(e1, _name, e2) => {
+            ExprKind::Set(e1, _name, e2) => {
                 self.resolve_exp(e1);
                 self.resolve_exp(e2);
             }
-            Expr::This(keyword) => {
+            ExprKind::Lambda(_name, params, body) => {
+                self.resolve_function(params, body, FunctionType::Function);
+            }
+            ExprKind::List(elements) => {
+                for e in elements {
+                    self.resolve_exp(e);
+                }
+            }
+            ExprKind::Map(entries) => {
+                for (key, value) in entries {
+                    self.resolve_exp(key);
+                    self.resolve_exp(value);
+                }
+            }
+            ExprKind::Index(e, _bracket, index) => {
+                self.resolve_exp(e);
+                self.resolve_exp(index);
+            }
+            ExprKind::SetIndex(e, _bracket, index, value) => {
+                self.resolve_exp(e);
+                self.resolve_exp(index);
+                self.resolve_exp(value);
+            }
+            ExprKind::Update(target, _op, value, _postfix) => {
+                self.resolve_exp(target);
+                self.resolve_exp(value);
+            }
+            ExprKind::Chain(e) | ExprKind::Optional(e) => {
+                self.resolve_exp(e);
+            }
+            ExprKind::This(keyword) => {
                 if self.current_class == ClassType::None {
-                    panic!("Can't use 'this' outside of a class.");
+                    self.error(
+                        Diagnostic::error(
+                            ErrorCode::ThisOutsideClass,
+                            "Can't use 'this' outside of a class.",
+                        )
+                        .with_span(keyword.identifier.span),
+                    );
                 }
                 self.resolve_var(keyword);
             }
-            Expr::Super(var, _method) => {
+            ExprKind::Super(var, _method) => {
+                match self.current_class {
+                    ClassType::None => self.error(
+                        Diagnostic::error(
+                            ErrorCode::SuperOutsideClass,
+                            "Can't use 'super' outside of a class.",
+                        )
+                        .with_span(var.identifier.span),
+                    ),
+                    ClassType::Class => self.error(
+                        Diagnostic::error(
+                            ErrorCode::SuperWithoutSuperclass,
+                            "Can't use 'super' in a class with no superclass.",
+                        )
+                        .with_span(var.identifier.span),
+                    ),
+                    ClassType::Subclass => (),
+                }
                 self.resolve_var(var);
             }
         }
@@ -190,21 +346,17 @@ impl Resolver {
 
 impl Resolver {
     fn resolve_var(&mut self, var: &mut Var) {
-        for (i, scope) in self.scopes.iter_mut().rev().enumerate() {
-            if scope.contains_key(var.name()) {
-                var.hops = i;
-                return;
-            }
-        }
-        var.hops = self.scopes.len();
+        var.hops = self
+            .scopes
+            .iter()
+            .rev()
+            .position(|scope| scope.contains_key(var.name()));
     }
 
-    fn resolve_function(
-        &mut self,
-        _name: &mut Token,
-        args: &mut [Token],
-        body: &mut Box<Statement>,
-    ) {
+    fn resolve_function(&mut self, args: &mut [Token], body: &mut Statement, kind: FunctionType) {
+        let enclosing_function = std::mem::replace(&mut self.current_function, kind);
+        let enclosing_loops = std::mem::take(&mut self.loop_depth);
+
         self.begin_scope();
         for param in args {
             self.declare(param);
@@ -212,6 +364,9 @@ impl Resolver {
         }
         self.resolve_stmt(body);
         self.end_scope();
+
+        self.current_function = enclosing_function;
+        self.loop_depth = enclosing_loops;
     }
 
     fn begin_scope(&mut self) {
@@ -223,21 +378,131 @@ impl Resolver {
     }
 
     fn declare(&mut self, name: &Token) {
-        if self.scopes.is_empty() {
+        let Some(scope) = self.scopes.last_mut() else {
+            return;
+        };
+
+        if let Some(previous) = scope.get(&name.lexeme) {
+            let err = Diagnostic::error(
+                ErrorCode::DuplicateDeclaration,
+                "Already a variable with this name in this scope.",
+            )
+            .with_span(name.span)
+            .with_label(previous.span, "first declared here");
+            self.error(err);
             return;
         }
 
-        let scope = self.scopes.last_mut().unwrap();
-        scope.insert(name.lexeme.clone(), false);
+        scope.insert(
+            name.lexeme.clone(),
+            Binding {
+                defined: false,
+                span: name.span,
+            },
+        );
     }
 
     fn define(&mut self, name: &Token) {
-        if self.scopes.is_empty() {
-            return;
-        }
-        self.scopes
+        if let Some(binding) = self
+            .scopes
             .last_mut()
-            .unwrap()
-            .insert(name.lexeme.clone(), true);
+            .and_then(|scope| scope.get_mut(&name.lexeme))
+        {
+            binding.defined = true;
+        }
+    }
+
+    // Adds an implicitly defined name like `this` to the innermost scope.
+    fn insert(&mut self, name: &str, span: Span) {
+        self.scopes.last_mut().unwrap().insert(
+            name.to_string(),
+            Binding {
+                defined: true,
+                span,
+            },
+        );
+    }
+
+    fn error(&mut self, diagnostic: Diagnostic) {
+        self.errors.push(diagnostic);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Parser, Scanner};
+
+    fn resolve(source: &str) -> Vec<ErrorCode> {
+        let mut scanner = Scanner::new(source.to_string());
+        scanner.scan_tokens().unwrap();
+        let mut stms = Parser::new(scanner.get_tokens()).parse().unwrap();
+        match Resolver::run(&mut stms) {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors.into_iter().map(|e| e.code).collect(),
+        }
+    }
+
+    #[test]
+    fn valid_program() {
+        let source = "var a = 1; var a = 2; fun f(x) { return x; }\n\
+                      class A { init() { return; } } class B < A { m() { super.m(); } }";
+        assert_eq!(resolve(source), Vec::new());
+    }
+
+    #[test]
+    fn collects_all_errors() {
+        let source = "return 1; print this; { var a = a; var a; }";
+        assert_eq!(
+            resolve(source),
+            vec![
+                ErrorCode::TopLevelReturn,
+                ErrorCode::ThisOutsideClass,
+                ErrorCode::LocalInOwnInitializer,
+                ErrorCode::DuplicateDeclaration,
+            ]
+        );
+    }
+
+    #[test]
+    fn class_errors() {
+        let source = "class A < A { init() { return 1; } }\n\
+                      class B { m() { super.m(); } }\n\
+                      fun f() { super.m(); }";
+        assert_eq!(
+            resolve(source),
+            vec![
+                ErrorCode::SelfInheritance,
+                ErrorCode::ReturnFromInitializer,
+                ErrorCode::SuperWithoutSuperclass,
+                ErrorCode::SuperOutsideClass,
+            ]
+        );
+    }
+
+    #[test]
+    fn locals_and_globals() {
+        let mut scanner = Scanner::new("var g; { var a; { a; g; } }".to_string());
+        scanner.scan_tokens().unwrap();
+        let mut stms = Parser::new(scanner.get_tokens()).parse().unwrap();
+        Resolver::run(&mut stms).unwrap();
+
+        let StatementKind::Block(outer) = &stms[1].kind else {
+            panic!("expected a block");
+        };
+        let StatementKind::Block(inner) = &outer[1].kind else {
+            panic!("expected a block");
+        };
+        let hops: Vec<Option<usize>> = inner
+            .iter()
+            .map(|stm| match &stm.kind {
+                StatementKind::Expr(Expr {
+                    kind: ExprKind::Variable(var),
+                    ..
+                }) => var.hops,
+                _ => panic!("expected a variable"),
+            })
+            .collect();
+        assert_eq!(hops, vec![Some(1), None]);
     }
 }
@@ -1,13 +1,23 @@
+use crate::diagnostic::{Diagnostic, ErrorCode};
 use crate::object::Object;
+use crate::span::Span;
 use crate::tokens::{Token, TokenType};
 use crate::LoxError;
 
 pub struct Scanner {
     source: Vec<char>,
     tokens: Vec<Token>,
+    // Char indices into `source`
     start: usize,
     current: usize,
+    // Byte offsets of `start` and `current`
+    start_byte: usize,
+    current_byte: usize,
+    // 1-based position of `start`
+    start_line: usize,
+    start_column: usize,
     line: usize,
+    line_start: usize,
 }
 
 impl Scanner {
@@ -17,7 +27,12 @@ impl Scanner {
             tokens: Vec::new(),
             start: 0,
             current: 0,
-            line: 0,
+            start_byte: 0,
+            current_byte: 0,
+            start_line: 1,
+            start_column: 1,
+            line: 1,
+            line_start: 0,
         }
     }
 
@@ -29,16 +44,25 @@ impl Scanner {
         self.current >= self.source.len()
     }
 
-    pub fn scan_tokens(&mut self) -> Result<&Vec<Token>, LoxError> {
+    /// Scans the whole source, reporting every lexical error instead of
+    /// stopping at the first one.
+    pub fn scan_tokens(&mut self) -> Result<&Vec<Token>, Vec<Diagnostic>> {
+        let mut errors = Vec::new();
         while !self.is_at_end() {
-            self.start = self.current;
-            self.scan_token()?;
+            self.begin_token();
+            if let Err(LoxError::Diagnostic(d)) = self.scan_token() {
+                errors.push(*d);
+            }
         }
 
-        self.tokens
-            .push(Token::new(TokenType::EOF, "", None, self.line));
+        self.begin_token();
+        self.add_token(TokenType::EOF);
 
-        Ok(&self.tokens)
+        if errors.is_empty() {
+            Ok(&self.tokens)
+        } else {
+            Err(errors)
+        }
     }
 
     fn scan_token(&mut self) -> Result<(), LoxError> {
@@ -47,12 +71,53 @@ impl Scanner {
             ')' => self.add_token(TokenType::RIGHT_PAREN),
             '{' => self.add_token(TokenType::LEFT_BRACE),
             '}' => self.add_token(TokenType::RIGHT_BRACE),
+            '[' => self.add_token(TokenType::LEFT_BRACKET),
+            ']' => self.add_token(TokenType::RIGHT_BRACKET),
             ',' => self.add_token(TokenType::COMMA),
+            ':' => self.add_token(TokenType::COLON),
             '.' => self.add_token(TokenType::DOT),
-            '-' => self.add_token(TokenType::MINUS),
-            '+' => self.add_token(TokenType::PLUS),
+            '-' => {
+                if self.verify('-') {
+                    self.add_token(TokenType::MINUS_MINUS)
+                } else if self.verify('=') {
+                    self.add_token(TokenType::MINUS_EQUAL)
+                } else {
+                    self.add_token(TokenType::MINUS)
+                }
+            }
+            '+' => {
+                if self.verify('+') {
+                    self.add_token(TokenType::PLUS_PLUS)
+                } else if self.verify('=') {
+                    self.add_token(TokenType::PLUS_EQUAL)
+                } else {
+                    self.add_token(TokenType::PLUS)
+                }
+            }
             ';' => self.add_token(TokenType::SEMICOLON),
-            '*' => self.add_token(TokenType::STAR),
+            '%' => self.add_token(TokenType::PERCENT),
+            '?' => {
+                if self.verify('?') {
+                    self.add_token(TokenType::QUESTION_QUESTION)
+                } else if self.verify('.') {
+                    self.add_token(TokenType::QUESTION_DOT)
+                } else {
+                    self.add_token(TokenType::QUESTION)
+                }
+            }
+            '*' => {
+                if self.verify('*') {
+                    self.add_token(TokenType::STAR_STAR)
+                } else if self.verify('=') {
+                    self.add_token(TokenType::STAR_EQUAL)
+                } else {
+                    self.add_token(TokenType::STAR)
+                }
+            }
+            '~' if self.peek() == Some('/') => {
+                self.advance();
+                self.add_token(TokenType::TILDE_SLASH)
+            }
             '!' => {
                 if self.verify('=') {
                     self.add_token(TokenType::BANG_EQUAL)
@@ -63,6 +128,8 @@ impl Scanner {
             '=' => {
                 if self.verify('=') {
                     self.add_token(TokenType::EQUAL_EQUAL)
+                } else if self.verify('>') {
+                    self.add_token(TokenType::ARROW)
                 } else {
                     self.add_token(TokenType::EQUAL)
                 }
@@ -90,22 +157,25 @@ impl Scanner {
                             self.advance();
                         }
                     }
+                } else if self.verify('=') {
+                    self.add_token(TokenType::SLASH_EQUAL)
                 } else {
                     self.add_token(TokenType::SLASH)
                 }
             }
-            ' ' | '\r' | '\t' => (),
-            '\n' => self.line += 1,
+            ' ' | '\r' | '\t' | '\n' => (),
             '\"' => self.string()?,
-            ch if ch.is_digit(10) => self.number()?,
+            ch if ch.is_ascii_digit() => self.number()?,
             ch if Self::is_alpha(ch) => {
                 self.identifier()?;
             }
             ch => {
-                return Err(LoxError::Error(format!(
-                    "Unexpected character '{}' in line {} !",
-                    ch, self.line
-                )))
+                return Err(Diagnostic::error(
+                    ErrorCode::UnexpectedCharacter,
+                    "Unexpected character.",
+                )
+                .with_span_label(self.span(), format!("'{}' isn't valid Lox", ch))
+                .into())
             }
         }
 
@@ -131,10 +201,16 @@ impl Scanner {
 
         if let Some(c) = self.peek() {
             if Self::is_alpha(c) {
-                return Err(LoxError::Error(format!(
-                    "Unexpected character '{}' in line {} !",
-                    c, self.line
-                )));
+                let number = self.span();
+                self.begin_token();
+                self.advance();
+                return Err(Diagnostic::error(
+                    ErrorCode::UnexpectedCharacter,
+                    format!("Unexpected character '{}'.", c),
+                )
+                .with_span_label(self.span(), "identifiers can't start with a digit")
+                .with_label(number, "number literal ends here")
+                .into());
             }
         }
 
@@ -155,19 +231,26 @@ impl Scanner {
 
         match s.as_str() {
             "and" => self.add_token(TokenType::AND),
+            "break" => self.add_token(TokenType::BREAK),
+            "catch" => self.add_token(TokenType::CATCH),
             "class" => self.add_token(TokenType::CLASS),
+            "continue" => self.add_token(TokenType::CONTINUE),
             "else" => self.add_token(TokenType::ELSE),
             "false" => self.add_token(TokenType::FALSE),
+            "finally" => self.add_token(TokenType::FINALLY),
             "for" => self.add_token(TokenType::FOR),
             "fun" => self.add_token(TokenType::FUN),
             "if" => self.add_token(TokenType::IF),
+            "import" => self.add_token(TokenType::IMPORT),
             "nil" => self.add_token(TokenType::NIL),
             "or" => self.add_token(TokenType::OR),
             "print" => self.add_token(TokenType::PRINT),
             "return" => self.add_token(TokenType::RETURN),
             "super" => self.add_token(TokenType::SUPER),
             "this" => self.add_token(TokenType::THIS),
+            "throw" => self.add_token(TokenType::THROW),
             "true" => self.add_token(TokenType::TRUE),
+            "try" => self.add_token(TokenType::TRY),
             "var" => self.add_token(TokenType::VAR),
             "while" => self.add_token(TokenType::WHILE),
             _ => self.add_token(TokenType::IDENTIFIER),
@@ -181,17 +264,16 @@ impl Scanner {
             if ch == '\"' {
                 break;
             }
-            if ch == '\n' {
-                self.line += 1;
-            }
-            self.current += 1;
+            self.advance();
         }
 
         if self.is_at_end() {
-            return Err(LoxError::Error(format!(
-                "Unterminated string at line {}!",
-                self.line
-            )));
+            return Err(
+                Diagnostic::error(ErrorCode::UnterminatedString, "Unterminated string.")
+                    .with_span_label(self.span(), "string starts here")
+                    .with_note("strings must be closed with '\"'")
+                    .into(),
+            );
         }
         // The closing "
         self.advance();
@@ -205,9 +287,30 @@ impl Scanner {
         Ok(())
     }
 
+    fn begin_token(&mut self) {
+        self.start = self.current;
+        self.start_byte = self.current_byte;
+        self.start_line = self.line;
+        self.start_column = self.current - self.line_start + 1;
+    }
+
+    fn span(&self) -> Span {
+        Span::new(
+            self.start_byte,
+            self.current_byte,
+            self.start_line,
+            self.start_column,
+        )
+    }
+
     fn advance(&mut self) -> char {
         let c = *self.source.get(self.current).unwrap();
         self.current += 1;
+        self.current_byte += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+            self.line_start = self.current;
+        }
 
         c
     }
@@ -262,13 +365,51 @@ impl Scanner {
 
     fn add_token(&mut self, token_type: TokenType) {
         let s: String = self.source[self.start..self.current].iter().collect();
-        let token = Token::new(token_type, s, None, self.line);
+        let token = Token::new(token_type, s, None, self.span());
         self.tokens.push(token);
     }
 
     fn add_token_object(&mut self, token_type: TokenType, object: Object) {
         let s: String = self.source[self.start..self.current].iter().collect();
-        let token = Token::new(token_type, s, Some(object), self.line);
+        let token = Token::new(token_type, s, Some(object), self.span());
         self.tokens.push(token);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scan(source: &str) -> Vec<Token> {
+        let mut scanner = Scanner::new(source.to_string());
+        scanner.scan_tokens().unwrap();
+        scanner.get_tokens()
+    }
+
+    #[test]
+    fn token_spans() {
+        let tokens = scan("var x = 1;\n  print x;");
+        let spans: Vec<Span> = tokens.iter().map(|t| t.span).collect();
+        assert_eq!(spans[0], Span::new(0, 3, 1, 1));
+        assert_eq!(spans[1], Span::new(4, 5, 1, 5));
+        assert_eq!(spans[4], Span::new(9, 10, 1, 10));
+        assert_eq!(spans[5], Span::new(13, 18, 2, 3));
+        assert_eq!(spans[7], Span::new(20, 21, 2, 10));
+        assert_eq!(tokens[8].token_type, TokenType::EOF);
+        assert_eq!(spans[8], Span::new(21, 21, 2, 11));
+    }
+
+    #[test]
+    fn multi_line_string_span_starts_at_quote() {
+        let tokens = scan("\"a\nb\" x");
+        assert_eq!(tokens[0].span, Span::new(0, 5, 1, 1));
+        assert_eq!(tokens[1].span, Span::new(6, 7, 2, 4));
+    }
+
+    #[test]
+    fn spans_are_byte_offsets() {
+        let tokens = scan("\"ü\" x");
+        assert_eq!(tokens[0].span, Span::new(0, 4, 1, 1));
+        assert_eq!(tokens[1].span, Span::new(5, 6, 1, 5));
+    }
+}
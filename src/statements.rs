@@ -1,17 +1,51 @@
 use crate::expressions::Var;
+use crate::span::Span;
 use crate::tokens::Token;
 use crate::Expr;
 use std::collections::VecDeque;
 
 #[derive(Debug, Clone, PartialEq)]
-pub enum Statement {
+pub struct Statement {
+    pub kind: StatementKind,
+    pub span: Span,
+}
+
+impl Statement {
+    pub fn new(kind: StatementKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
+
+/// What an import binds in the importing scope.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Imports {
+    Module(Token),
+    Names(Vec<Token>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StatementKind {
     VarDecl(Token, Expr),
     ClassDecl(Token, Option<Var>, Vec<Statement>),
     Expr(Expr),
     Print(Expr),
     Block(VecDeque<Statement>),
     If(Expr, Box<Statement>, Option<Box<Statement>>),
-    While(Expr, Box<Statement>),
+    /// A loop. A `for` loop's increment is kept apart from the body so
+    /// `continue` still runs it.
+    While(Expr, Box<Statement>, Option<Box<Expr>>),
     FuncDecl(Token, Vec<Token>, Box<Statement>),
-    Return(Expr),
+    Return(Option<Expr>),
+    Break,
+    Continue,
+    /// `import "path" as name;` or `from "path" import a, b;`. The token
+    /// is the path's string literal.
+    Import(Token, Imports),
+    Throw(Expr),
+    /// `try` block, optional `catch (name)` block, optional `finally` block.
+    Try(
+        Box<Statement>,
+        Option<(Token, Box<Statement>)>,
+        Option<Box<Statement>>,
+    ),
 }
@@ -0,0 +1,117 @@
+//! Built-in methods of strings, and the `str` and `num` conversions.
+//!
+//! Positions and lengths count characters, not bytes.
+
+use crate::callable::{Args, NativeFunction};
+use crate::diagnostic::{Diagnostic, ErrorCode};
+use crate::tokens::Token;
+use crate::{LoxError, LoxList, Object};
+
+use std::rc::Rc;
+
+/// Returns the built-in method `name` bound to the string `s`.
+pub fn method(s: &str, name: &Token) -> Result<Object, LoxError> {
+    let s = s.to_string();
+    let method = match name.lexeme.as_str() {
+        "len" => NativeFunction::new("len", 0, move |_| Ok(s.chars().count())),
+        "upper" => NativeFunction::new("upper", 0, move |_| Ok(s.to_uppercase())),
+        "lower" => NativeFunction::new("lower", 0, move |_| Ok(s.to_lowercase())),
+        "trim" => NativeFunction::new("trim", 0, move |_| Ok(s.trim().to_string())),
+        "chars" => NativeFunction::new("chars", 0, move |_| Ok(chars(&s))),
+        "split" => NativeFunction::new("split", 1, move |args| {
+            let separator = args.string(0)?;
+            if separator.is_empty() {
+                return Ok(chars(&s));
+            }
+            Ok(LoxList::new(
+                s.split(separator.as_str())
+                    .map(|part| Object::String(part.to_string()))
+                    .collect(),
+            ))
+        }),
+        "contains" => NativeFunction::new("contains", 1, move |args| {
+            Ok(s.contains(args.string(0)?.as_str()))
+        }),
+        "find" => NativeFunction::new("find", 1, move |args| {
+            let needle = args.string(0)?;
+            Ok(s.find(needle.as_str())
+                .map(|byte| s[..byte].chars().count()))
+        }),
+        "replace" => NativeFunction::new("replace", 2, move |args| {
+            Ok(s.replace(args.string(0)?.as_str(), args.string(1)?.as_str()))
+        }),
+        "substring" => NativeFunction::variadic("substring", 1, move |args| substring(&s, args)),
+        _ => {
+            return Err(Diagnostic::error(
+                ErrorCode::UndefinedProperty,
+                format!("Undefined property '{}'.", name.lexeme),
+            )
+            .with_span(name.span)
+            .into())
+        }
+    };
+    Ok(Object::Callable(Rc::new(Box::new(method))))
+}
+
+fn chars(s: &str) -> LoxList {
+    LoxList::new(s.chars().map(|c| Object::String(c.to_string())).collect())
+}
+
+// `substring(start)` or `substring(start, end)`, end exclusive.
+fn substring(s: &str, args: &Args) -> Result<String, LoxError> {
+    if args.len() > 2 {
+        return Err(LoxError::new(
+            ErrorCode::ArityMismatch,
+            format!("Expected at most 2 arguments but got {}.", args.len()),
+        ));
+    }
+    let len = s.chars().count();
+    let end = match args.get(1) {
+        Some(_) => args.arg::<usize>(1)?,
+        None => len,
+    };
+    let start = args.arg::<usize>(0)?;
+    if start > end || end > len {
+        return Err(Diagnostic::error(
+            ErrorCode::IndexOutOfRange,
+            format!("Range {}..{} is out of range.", start, end),
+        )
+        .with_note(format!("the string has length {}", len))
+        .into());
+    }
+    Ok(s.chars().skip(start).take(end - start).collect())
+}
+
+/// `str(value)`: the value as `print` would show it.
+pub(crate) fn str() -> NativeFunction {
+    NativeFunction::new("str", 1, |args| Ok(args[0].to_string()))
+}
+
+/// `num(value)`: parses a string as a number, or returns nil if it isn't
+/// one. Numbers are returned as they are.
+pub(crate) fn num() -> NativeFunction {
+    NativeFunction::new("num", 1, |args| match &args[0] {
+        Object::Number(n) => Ok(Some(*n)),
+        Object::String(s) => Ok(parse_number(s.trim())),
+        value => Err(LoxError::new(
+            ErrorCode::TypeMismatch,
+            format!("Can't convert {} to a number.", value.repr()),
+        )),
+    })
+}
+
+// Accepts what the scanner reads as a number literal, digits optionally
+// followed by a '.' and more digits, with an optional leading '-' so
+// negative numbers convert back from `str`.
+fn parse_number(s: &str) -> Option<f64> {
+    let unsigned = s.strip_prefix('-').unwrap_or(s);
+    let (whole, fraction) = match unsigned.split_once('.') {
+        Some((whole, fraction)) => (whole, Some(fraction)),
+        None => (unsigned, None),
+    };
+    let digits = |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit());
+    if !digits(whole) || !fraction.is_none_or(digits) {
+        return None;
+    }
+    s.parse().ok()
+}
@@ -0,0 +1,241 @@
+//! Runs `.lox` files annotated in the format of the Crafting Interpreters
+//! test suite and checks what they print and which errors they raise.
+//!
+//! ```text
+//! print 1 + 2;   // expect: 3
+//! print -nil;    // expect runtime error: Operand must be a number.
+//! var;           // Error at ';': Expect variable name.
+//! // [line 9] Error at end: Expect '}' after block.
+//! ```
+//!
+//! Compile errors are matched on their line and message. The `at '...'`
+//! part is not compared: rlox reports exact spans instead.
+
+use crate::{EvalError, Interpreter, OutputBuffer};
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Directories of the official suite that don't apply to a tree-walker:
+/// chapter-specific tests and benchmarks.
+const SKIPPED_DIRS: &[&str] = &["benchmark", "expressions", "scanning"];
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Expectations {
+    pub output: Vec<String>,
+    pub compile_errors: Vec<(usize, String)>,
+    pub runtime_error: Option<(usize, String)>,
+}
+
+impl Expectations {
+    pub fn parse(source: &str) -> Self {
+        let mut expectations = Self::default();
+        for (index, line) in source.lines().enumerate() {
+            let line_number = index + 1;
+            let Some((_, comment)) = line.split_once("// ") else {
+                continue;
+            };
+
+            if let Some(output) = comment.strip_prefix("expect: ") {
+                expectations.output.push(output.to_string());
+            } else if let Some(message) = comment.strip_prefix("expect runtime error: ") {
+                expectations.runtime_error = Some((line_number, message.to_string()));
+            } else if let Some((line, message)) = Self::compile_error(comment) {
+                let line = line.unwrap_or(line_number);
+                expectations.compile_errors.push((line, message));
+            }
+        }
+        expectations
+    }
+
+    // Parses `[line N] Error at 'x': message`, also spelled `[java line N]`
+    // or without the line prefix. `[c line N]` belongs to clox and is ignored.
+    fn compile_error(comment: &str) -> Option<(Option<usize>, String)> {
+        let (line, error) = match comment.strip_prefix('[') {
+            Some(rest) => {
+                let (prefix, error) = rest.split_once("] ")?;
+                let number = match prefix.strip_prefix("java ").unwrap_or(prefix) {
+                    p if p.starts_with("c ") => return None,
+                    p => p.strip_prefix("line ")?.parse().ok()?,
+                };
+                (Some(number), error)
+            }
+            None => (None, comment),
+        };
+
+        let error = error.strip_prefix("Error")?;
+        let message = match error.strip_prefix(": ") {
+            Some(message) => message,
+            None if error.starts_with(" at '") => error[5..].split_once("': ")?.1,
+            None => error.strip_prefix(" at end: ")?,
+        };
+        Some((line, message.to_string()))
+    }
+}
+
+/// Runs `source` and returns one message per unmet expectation.
+pub fn check(source: &str) -> Vec<String> {
+    check_script(Path::new(""), source)
+}
+
+/// Like `check`, for the contents of the file at `path` so its imports
+/// resolve.
+pub fn check_script(path: &Path, source: &str) -> Vec<String> {
+    let expected = Expectations::parse(source);
+    let out = OutputBuffer::new();
+    let mut lox = Interpreter::builder()
+        .stdout(out.clone())
+        .stderr(io::sink())
+        .build();
+    let result = lox.eval_script(path, source);
+
+    let mut failures = Vec::new();
+    let output = out.contents();
+    let actual: Vec<&str> = output.lines().collect();
+    for (i, expected) in expected.output.iter().enumerate() {
+        match actual.get(i) {
+            Some(actual) if actual == expected => (),
+            Some(actual) => {
+                failures.push(format!("expected output '{}', got '{}'", expected, actual))
+            }
+            None => failures.push(format!("missing expected output '{}'", expected)),
+        }
+    }
+    for extra in actual.iter().skip(expected.output.len()) {
+        failures.push(format!("unexpected output '{}'", extra));
+    }
+
+    let mut errors: Vec<(usize, String)> = Vec::new();
+    let mut runtime_error = None;
+    match result {
+        Ok(_) => (),
+        Err(EvalError::Compile(diagnostics)) => {
+            errors = diagnostics
+                .into_iter()
+                .map(|d| (d.span().map_or(0, |s| s.line), d.message))
+                .collect();
+        }
+        Err(EvalError::Runtime(e)) => {
+            runtime_error = Some((e.script_span().map_or(0, |s| s.line), e.diagnostic.message));
+        }
+    }
+
+    for error in &expected.compile_errors {
+        if let Some(i) = errors.iter().position(|e| e == error) {
+            errors.remove(i);
+        } else {
+            failures.push(format!(
+                "missing expected error [line {}] {}",
+                error.0, error.1
+            ));
+        }
+    }
+    for (line, message) in errors {
+        failures.push(format!("unexpected error [line {}] {}", line, message));
+    }
+
+    if runtime_error != expected.runtime_error {
+        match (&expected.runtime_error, runtime_error) {
+            (Some((line, message)), None) => failures.push(format!(
+                "missing expected runtime error [line {}] {}",
+                line, message
+            )),
+            (_, Some((line, message))) => failures.push(format!(
+                "unexpected runtime error [line {}] {}",
+                line, message
+            )),
+            (None, None) => unreachable!(),
+        }
+    }
+
+    failures
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub passed: usize,
+    pub failures: Vec<(PathBuf, Vec<String>)>,
+}
+
+impl Report {
+    pub fn is_success(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Checks every `.lox` file under `dir`, in path order.
+pub fn run_dir(dir: &Path) -> io::Result<Report> {
+    let mut files = Vec::new();
+    collect(dir, &mut files)?;
+    files.sort();
+
+    let mut report = Report::default();
+    for path in files {
+        let failures = check_script(&path, &fs::read_to_string(&path)?);
+        if failures.is_empty() {
+            report.passed += 1;
+        } else {
+            report.failures.push((path, failures));
+        }
+    }
+    Ok(report)
+}
+
+fn collect(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            let skipped = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| SKIPPED_DIRS.contains(&name));
+            if !skipped {
+                collect(&path, files)?;
+            }
+        } else if path.extension().is_some_and(|ext| ext == "lox") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_annotations() {
+        let source = "print 1; // expect: 1\n\
+                      -nil; // expect runtime error: Operand must be a number.\n\
+                      var; // Error at ';': Expect variable name.\n\
+                      // [line 9] Error at end: Expect '}' after block.\n\
+                      // [java line 5] Error: Unexpected character.\n\
+                      // [c line 6] Error: Unexpected character.";
+        assert_eq!(
+            Expectations::parse(source),
+            Expectations {
+                output: vec!["1".to_string()],
+                compile_errors: vec![
+                    (3, "Expect variable name.".to_string()),
+                    (9, "Expect '}' after block.".to_string()),
+                    (5, "Unexpected character.".to_string()),
+                ],
+                runtime_error: Some((2, "Operand must be a number.".to_string())),
+            }
+        );
+    }
+
+    #[test]
+    fn reports_mismatches() {
+        assert!(check("print 1; // expect: 1").is_empty());
+        assert_eq!(
+            check("print 2; // expect: 1\nprint 3;"),
+            vec!["expected output '1', got '2'", "unexpected output '3'"]
+        );
+        assert_eq!(
+            check("print nil + 1;"),
+            vec!["unexpected runtime error [line 1] Operands must be two numbers or two strings."]
+        );
+    }
+}
@@ -1,15 +1,17 @@
 use crate::object::Object;
+use crate::span::Span;
+use std::fmt;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Token {
     pub token_type: TokenType,
     pub lexeme: String,
     pub literal: Option<Object>,
-    pub line: usize,
+    pub span: Span,
 }
 
 impl Token {
-    pub fn new<T>(token_type: TokenType, lexeme: T, literal: Option<Object>, line: usize) -> Self
+    pub fn new<T>(token_type: TokenType, lexeme: T, literal: Option<Object>, span: Span) -> Self
     where
         T: Into<String>,
     {
@@ -17,14 +19,14 @@ impl Token {
             token_type,
             lexeme: lexeme.into(),
             literal,
-            line,
+            span,
         }
     }
 }
 
-impl ToString for Token {
-    fn to_string(&self) -> String {
-        self.lexeme.clone()
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.lexeme)
     }
 }
 
@@ -36,13 +38,18 @@ pub enum TokenType {
     RIGHT_PAREN,
     LEFT_BRACE,
     RIGHT_BRACE,
+    LEFT_BRACKET,
+    RIGHT_BRACKET,
     COMMA,
+    COLON,
     DOT,
     MINUS,
     PLUS,
     SEMICOLON,
     SLASH,
     STAR,
+    PERCENT,
+    QUESTION,
 
     // One or two character tokens
     BANG,
@@ -53,6 +60,17 @@ pub enum TokenType {
     GREATER_EQUAL,
     LESS,
     LESS_EQUAL,
+    ARROW,
+    STAR_STAR,
+    TILDE_SLASH,
+    PLUS_EQUAL,
+    MINUS_EQUAL,
+    STAR_EQUAL,
+    SLASH_EQUAL,
+    PLUS_PLUS,
+    MINUS_MINUS,
+    QUESTION_QUESTION,
+    QUESTION_DOT,
 
     // Literals
     IDENTIFIER,
@@ -61,19 +79,26 @@ pub enum TokenType {
 
     // Keywords
     AND,
+    BREAK,
+    CATCH,
     CLASS,
+    CONTINUE,
     ELSE,
     FALSE,
+    FINALLY,
     FUN,
     FOR,
     IF,
+    IMPORT,
     NIL,
     OR,
     PRINT,
     RETURN,
     SUPER,
     THIS,
+    THROW,
     TRUE,
+    TRY,
     VAR,
     WHILE,
 
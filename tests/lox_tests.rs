@@ -0,0 +1,21 @@
+use rlox::test_runner;
+use std::path::Path;
+
+#[test]
+fn golden_files() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/lox");
+    let report = test_runner::run_dir(&dir).unwrap();
+
+    for (path, failures) in &report.failures {
+        eprintln!("FAIL {}", path.display());
+        for failure in failures {
+            eprintln!("     {}", failure);
+        }
+    }
+    assert!(
+        report.is_success(),
+        "{} golden files failed",
+        report.failures.len()
+    );
+    assert!(report.passed > 0);
+}